@@ -1,7 +1,7 @@
 use std::{
     env,
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
     process::Command,
 };
@@ -10,6 +10,10 @@ mod attacks;
 mod magics;
 mod maps;
 
+#[allow(dead_code)]
+#[path = "../src/nnue/format.rs"]
+mod format;
+
 const BASE_URL: &str = "https://github.com/codedeliveryservice/RecklessNetworks/releases/download/networks";
 const NETWORK_NAME: &str = "v60-7f587dfb.nnue";
 
@@ -62,7 +66,34 @@ fn generate_model_env() {
         path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
     }
 
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    // The header is embedded separately so that the parameters can be transmuted in place.
+    let dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let header_path = dir.join("model.header");
+
+    let mut magic = [0; format::MAGIC.len()];
+    let has_header = File::open(&path).and_then(|mut file| file.read_exact(&mut magic)).is_ok()
+        && format::Header::is_present(&magic);
+
+    if has_header {
+        let bytes = std::fs::read(&path).unwrap();
+        let (header, parameters) = bytes.split_at(format::HEADER_SIZE);
+
+        // Checked here rather than on every startup, as hashing the whole network takes a while.
+        let expected = format::Header::parse(header).unwrap().content_hash;
+        let found = format::content_hash(parameters);
+        assert_eq!(expected, found, "The network '{}' fails its checksum", path.display());
+
+        std::fs::write(&header_path, header).unwrap();
+        path = dir.join("model.bin");
+        std::fs::write(&path, parameters).unwrap();
+    } else {
+        std::fs::write(&header_path, []).unwrap();
+    }
+
     println!("cargo:rustc-env=MODEL={}", path.display());
+    println!("cargo:rustc-env=MODEL_HEADER={}", header_path.display());
 }
 
 fn generate_attack_maps() {
@@ -2,7 +2,7 @@ use crate::{
     lookup::{
         between, bishop_attacks, king_attacks, knight_attacks, queen_attacks, ray_pass, relative_diagonal, rook_attacks,
     },
-    types::{Bitboard, CastlingKind, File, MoveKind, MoveList, PieceType, Square},
+    types::{Bitboard, CastlingKind, File, Move, MoveKind, MoveList, PieceType, Square},
 };
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -18,6 +18,11 @@ impl super::Board {
         !list.is_empty()
     }
 
+    /// Finds the legal move written as `uci_move` in UCI notation.
+    pub fn find_uci_move(&self, uci_move: &str) -> Option<Move> {
+        self.generate_all_moves().iter().map(|entry| entry.mv).find(|mv| mv.to_uci(self) == uci_move)
+    }
+
     pub fn generate_all_moves(&self) -> MoveList {
         let mut list = MoveList::new();
         self.append_all_moves(&mut list);
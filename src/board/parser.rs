@@ -1,7 +1,7 @@
 use super::Board;
 use crate::{
     lookup::{between, ray_pass},
-    types::{CastlingKind, Color, HOME_RANK, KING_TO_FILE, Piece, PieceType, ROOK_TO_FILE, Square},
+    types::{CastlingKind, Color, File, HOME_RANK, KING_TO_FILE, Move, Piece, PieceType, ROOK_TO_FILE, Square},
 };
 
 #[derive(Debug)]
@@ -14,6 +14,22 @@ pub enum ParseFenError {
     InvalidActiveColor,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseSanError {
+    /// The SAN string is empty.
+    Empty,
+    /// The SAN string does not end with a valid destination square.
+    InvalidSquare,
+    /// The SAN string names an invalid promotion piece.
+    InvalidPromotion,
+    /// The SAN string contains characters that are not part of a move.
+    InvalidSyntax,
+    /// No legal move matches the SAN string.
+    IllegalMove,
+    /// More than one legal move matches the SAN string.
+    AmbiguousMove,
+}
+
 impl Board {
     /// Parses a [Forsyth–Edwards Notation][fen] string into a `Board`.
     ///
@@ -115,6 +131,90 @@ impl Board {
         self.castling_rooks[kind] = rook_from;
     }
 
+    /// Parses a move in [Standard Algebraic Notation][san] into a legal move.
+    ///
+    /// Check, mate and annotation suffixes are ignored, castling may be written
+    /// with letters or zeros, and the `=` before a promotion piece is optional.
+    ///
+    /// [san]: https://en.wikipedia.org/wiki/Algebraic_notation_(chess)
+    pub fn parse_san(&self, san: &str) -> Result<Move, ParseSanError> {
+        let san = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if san.is_empty() {
+            return Err(ParseSanError::Empty);
+        }
+
+        let moves = self.generate_all_moves();
+        let mut legal = moves.iter().map(|entry| entry.mv);
+
+        if let "O-O" | "0-0" | "O-O-O" | "0-0-0" = san {
+            let kingside = san.len() == 3;
+            return legal
+                .find(|mv| mv.is_castling() && (mv.to().file() == File::G) == kingside)
+                .ok_or(ParseSanError::IllegalMove);
+        }
+
+        let (piece_type, rest) = match san.as_bytes()[0] {
+            b'N' => (PieceType::Knight, &san[1..]),
+            b'B' => (PieceType::Bishop, &san[1..]),
+            b'R' => (PieceType::Rook, &san[1..]),
+            b'Q' => (PieceType::Queen, &san[1..]),
+            b'K' => (PieceType::King, &san[1..]),
+            _ => (PieceType::Pawn, san),
+        };
+
+        let (rest, promotion) = match rest.as_bytes() {
+            [head @ .., b'=', piece] | [head @ .., piece @ (b'N' | b'B' | b'R' | b'Q')] => {
+                let promotion = match piece {
+                    b'N' => PieceType::Knight,
+                    b'B' => PieceType::Bishop,
+                    b'R' => PieceType::Rook,
+                    b'Q' => PieceType::Queen,
+                    _ => return Err(ParseSanError::InvalidPromotion),
+                };
+                (&rest[..head.len()], Some(promotion))
+            }
+            _ => (rest, None),
+        };
+
+        if promotion.is_some() && piece_type != PieceType::Pawn {
+            return Err(ParseSanError::InvalidPromotion);
+        }
+
+        if rest.len() < 2 || !rest.is_char_boundary(rest.len() - 2) {
+            return Err(ParseSanError::InvalidSquare);
+        }
+
+        let (prefix, to) = rest.split_at(rest.len() - 2);
+        let to = Square::try_from(to).map_err(|()| ParseSanError::InvalidSquare)?;
+
+        let is_capture = prefix.ends_with('x');
+        let prefix = prefix.strip_suffix('x').unwrap_or(prefix);
+
+        let (from_file, from_rank) = match prefix.as_bytes() {
+            [] => (None, None),
+            [file @ b'a'..=b'h'] => (Some(file - b'a'), None),
+            [rank @ b'1'..=b'8'] => (None, Some(rank - b'1')),
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (Some(file - b'a'), Some(rank - b'1')),
+            _ => return Err(ParseSanError::InvalidSyntax),
+        };
+
+        let mut candidates = legal.filter(|&mv| {
+            !mv.is_castling()
+                && mv.to() == to
+                && self.moved_piece(mv).piece_type() == piece_type
+                && (!is_capture || mv.is_capture())
+                && promotion == mv.is_promotion().then(|| mv.promo_piece_type())
+                && from_file.is_none_or(|file| mv.from().file() as u8 == file)
+                && from_rank.is_none_or(|rank| mv.from().rank() as u8 == rank)
+        });
+
+        let mv = candidates.next().ok_or(ParseSanError::IllegalMove)?;
+        match candidates.next() {
+            Some(_) => Err(ParseSanError::AmbiguousMove),
+            None => Ok(mv),
+        }
+    }
+
     pub fn to_fen(&self) -> String {
         let mut fen = String::new();
 
@@ -1,12 +1,12 @@
 use std::sync::Once;
 
-use super::{Board, NullBoardObserver};
+use super::{Board, NullBoardObserver, parser::ParseSanError};
 use crate::lookup;
 
 static LUT_INITIALIZED: Once = Once::new();
 
 fn prepare_lut() {
-    LUT_INITIALIZED.call_once(|| lookup::initialize());
+    LUT_INITIALIZED.call_once(lookup::initialize);
 }
 
 macro_rules! assert_perft {
@@ -138,3 +138,65 @@ fn halfmove_clock_saturates_instead_of_overflowing() {
     assert_eq!(board.fiftymove_clock(), 255);
     let _ = board.hash();
 }
+
+#[test]
+fn to_san_disambiguates_and_marks_checks() {
+    prepare_lut();
+
+    let san = |fen: &str, uci: &str| {
+        let board = Board::from_fen(fen).unwrap();
+        board.find_uci_move(uci).unwrap().to_san(&board)
+    };
+
+    assert_eq!(san("7k/8/8/8/8/8/8/R4RK1 w - - 0 1", "a1d1"), "Rad1");
+    assert_eq!(san("7k/8/8/R7/8/8/8/R5K1 w - - 0 1", "a1a3"), "R1a3");
+    assert_eq!(san("8/7k/8/8/8/Q1Q5/8/Q5K1 w - - 0 1", "a3b2"), "Qa3b2");
+    assert_eq!(san("k7/p7/8/8/8/8/1R6/4K2R w K - 0 1", "h1h8"), "Rh8#");
+    assert_eq!(san("k7/p7/8/8/8/8/1R6/4K2R w K - 0 1", "b2b8"), "Rb8+");
+    assert_eq!(san("k7/p7/8/8/8/8/1R6/4K2R w K - 0 1", "e1g1"), "O-O");
+    assert_eq!(san("8/1P4k1/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    assert_eq!(san("8/1P4k1/8/3pP3/8/8/8/4K3 w - d6 0 1", "b7b8q"), "b8=Q");
+}
+
+#[test]
+fn to_san_writes_chess960_castling_as_o_o() {
+    prepare_lut();
+
+    let board = frc_board("r3k2r/8/8/8/8/8/8/1R2K1R1 w GB - 0 1");
+    assert_eq!(board.find_uci_move("e1g1").unwrap().to_san(&board), "O-O");
+    assert_eq!(board.find_uci_move("e1b1").unwrap().to_san(&board), "O-O-O");
+}
+
+#[test]
+fn parse_san_round_trips_legal_moves() {
+    prepare_lut();
+
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/7k/8/8/8/Q1Q5/8/Q5K1 w - - 0 1",
+    ] {
+        let board = Board::from_fen(fen).unwrap();
+        for entry in board.generate_all_moves().iter() {
+            assert_eq!(board.parse_san(&entry.mv.to_san(&board)), Ok(entry.mv));
+        }
+    }
+}
+
+#[test]
+fn parse_san_reports_errors() {
+    prepare_lut();
+
+    let board = Board::from_fen("7k/1P6/8/R7/8/8/8/R5K1 w - - 0 1").unwrap();
+
+    assert_eq!(board.parse_san(""), Err(ParseSanError::Empty));
+    assert_eq!(board.parse_san("Rd"), Err(ParseSanError::InvalidSquare));
+    assert_eq!(board.parse_san("Rzza3"), Err(ParseSanError::InvalidSyntax));
+    assert_eq!(board.parse_san("b8=K"), Err(ParseSanError::InvalidPromotion));
+    assert_eq!(board.parse_san("Nc3"), Err(ParseSanError::IllegalMove));
+    assert_eq!(board.parse_san("O-O"), Err(ParseSanError::IllegalMove));
+    assert_eq!(board.parse_san("Ra3"), Err(ParseSanError::AmbiguousMove));
+    assert_eq!(board.parse_san("R1a3+"), Ok(board.find_uci_move("a1a3").unwrap()));
+    assert_eq!(board.parse_san("b8Q"), Ok(board.find_uci_move("b7b8q").unwrap()));
+}
@@ -0,0 +1,146 @@
+use crate::{
+    board::Board,
+    types::{Color, cp_to_score},
+};
+
+/// The largest automatic contempt, in centipawns.
+const MAX_AUTO_CONTEMPT: i32 = 50;
+
+/// Rating difference, in Elo, that adds one centipawn of automatic contempt.
+const ELO_PER_CENTIPAWN: i32 = 10;
+
+/// Which sides keep their contempt while analysing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AnalysisContempt {
+    Off,
+    White,
+    Black,
+    Both,
+}
+
+#[derive(Copy, Clone)]
+pub struct Contempt {
+    /// Draw aversion of the engine in centipawns; negative values make it seek draws.
+    pub value: i32,
+    pub analysis: AnalysisContempt,
+    pub analyse_mode: bool,
+    /// Elo rating of the opponent as reported by `UCI_Opponent`.
+    pub opponent_elo: Option<i32>,
+    /// Rating advantage of the engine over the opponent as reported by `UCI_RatingAdv`.
+    /// Takes precedence over `opponent_elo` when set; see [`parse_rating_adv`].
+    pub rating_adv: Option<i32>,
+}
+
+impl Default for Contempt {
+    fn default() -> Self {
+        Self {
+            value: 0,
+            analysis: AnalysisContempt::Off,
+            analyse_mode: false,
+            opponent_elo: None,
+            rating_adv: None,
+        }
+    }
+}
+
+impl Contempt {
+    /// Returns the contempt in centipawns from the point of view of the side to move at the root.
+    /// `elo` is the current playing strength of the engine, used together with `UCI_Opponent`.
+    pub fn centipawns(&self, root: Color, elo: i32, infinite: bool) -> i32 {
+        let rating_adv = self.rating_adv.or(self.opponent_elo.map(|opponent| elo - opponent));
+        let auto = rating_adv.map_or(0, |adv| (adv / ELO_PER_CENTIPAWN).clamp(-MAX_AUTO_CONTEMPT, MAX_AUTO_CONTEMPT));
+        let contempt = self.value + auto;
+
+        if !self.analyse_mode && !infinite {
+            return contempt;
+        }
+
+        match self.analysis {
+            AnalysisContempt::Off => 0,
+            AnalysisContempt::Both => contempt,
+            AnalysisContempt::White if root == Color::White => contempt,
+            AnalysisContempt::Black if root == Color::Black => contempt,
+            _ => -contempt,
+        }
+    }
+
+    /// Returns the draw score for each side to move, in internal score units.
+    pub fn draw_scores(&self, board: &Board, elo: i32, infinite: bool) -> [i32; 2] {
+        let root = board.side_to_move();
+        let contempt = cp_to_score(self.centipawns(root, elo, infinite), board);
+
+        let mut scores = [0; 2];
+        scores[root] = -contempt;
+        scores[!root] = contempt;
+        scores
+    }
+}
+
+/// Parses the rating out of `UCI_Opponent` (`<title> <elo> <computer|human> <name>`),
+/// or returns `None` if it is missing or reported as `none`.
+pub fn parse_opponent(value: &str) -> Option<i32> {
+    value.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Interprets a `UCI_RatingAdv` value. Zero is the option default, which GUIs send along with
+/// every other option, so it counts as unset and leaves `UCI_Opponent` in charge.
+pub const fn parse_rating_adv(value: i64) -> Option<i32> {
+    if value == 0 { None } else { Some(value as i32) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skill::MAX_ELO;
+
+    #[test]
+    fn draw_scores_are_asymmetric() {
+        let contempt = Contempt { value: 20, ..Default::default() };
+
+        let white = contempt.draw_scores(&Board::starting_position(), MAX_ELO, false);
+        assert!(white[Color::White] < 0);
+        assert_eq!(white[Color::White], -white[Color::Black]);
+
+        let black = contempt.draw_scores(&Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap(), MAX_ELO, false);
+        assert!(black[Color::Black] < 0);
+    }
+
+    #[test]
+    fn analysis_contempt() {
+        let contempt = Contempt { value: 20, analyse_mode: true, ..Default::default() };
+        assert_eq!(contempt.centipawns(Color::White, MAX_ELO, false), 0);
+
+        let white = Contempt { analysis: AnalysisContempt::White, ..contempt };
+        assert_eq!(white.centipawns(Color::White, MAX_ELO, false), 20);
+        assert_eq!(white.centipawns(Color::Black, MAX_ELO, false), -20);
+
+        let both = Contempt { analysis: AnalysisContempt::Both, ..contempt };
+        assert_eq!(both.centipawns(Color::Black, MAX_ELO, false), 20);
+    }
+
+    #[test]
+    fn contempt_from_opponent() {
+        let opponent = Contempt {
+            opponent_elo: parse_opponent("none 2790 human Some Player"),
+            ..Default::default()
+        };
+        assert_eq!(opponent.centipawns(Color::White, 3190, false), 40);
+        assert_eq!(parse_opponent("none none computer Engine"), None);
+
+        let weaker = Contempt { rating_adv: Some(-10000), ..opponent };
+        assert_eq!(weaker.centipawns(Color::White, 3190, false), -50);
+    }
+
+    #[test]
+    fn default_rating_adv_leaves_opponent_in_charge() {
+        let contempt = Contempt {
+            opponent_elo: parse_opponent("none 2790 human Some Player"),
+            rating_adv: parse_rating_adv(0),
+            ..Default::default()
+        };
+        assert_eq!(contempt.centipawns(Color::White, 3190, false), 40);
+
+        let weaker = Contempt { rating_adv: parse_rating_adv(-400), ..contempt };
+        assert_eq!(weaker.centipawns(Color::White, 3190, false), -40);
+    }
+}
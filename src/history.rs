@@ -14,7 +14,8 @@ unsafe impl<T: Send> Send for HugeBox<T> {}
 unsafe impl<T: Sync> Sync for HugeBox<T> {}
 
 impl<T> HugeBox<T> {
-    fn new_zeroed() -> Self {
+    /// Allocates a zeroed `T`, or returns `None` if the memory is not available.
+    fn try_new_zeroed() -> Option<Self> {
         #[cfg(target_os = "linux")]
         let ptr = unsafe {
             use libc::{MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, PROT_READ, PROT_WRITE, madvise, mmap};
@@ -22,7 +23,7 @@ impl<T> HugeBox<T> {
             assert!(size > 0, "HugeBox requires a non-zero-sized type");
             let p = mmap(std::ptr::null_mut(), size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
             if p == MAP_FAILED {
-                std::alloc::handle_alloc_error(std::alloc::Layout::new::<T>());
+                return None;
             }
             madvise(p, size, MADV_HUGEPAGE);
             std::ptr::NonNull::new_unchecked(p.cast::<T>())
@@ -31,14 +32,10 @@ impl<T> HugeBox<T> {
         #[cfg(not(target_os = "linux"))]
         let ptr = unsafe {
             let layout = std::alloc::Layout::new::<T>();
-            let p = std::alloc::alloc_zeroed(layout);
-            if p.is_null() {
-                std::alloc::handle_alloc_error(layout);
-            }
-            std::ptr::NonNull::new_unchecked(p.cast::<T>())
+            std::ptr::NonNull::new(std::alloc::alloc_zeroed(layout).cast::<T>())?
         };
 
-        HugeBox { ptr }
+        Some(HugeBox { ptr })
     }
 }
 
@@ -218,9 +215,9 @@ impl ContinuationCorrectionHistory {
     }
 }
 
-impl Default for ContinuationCorrectionHistory {
-    fn default() -> Self {
-        Self { entries: HugeBox::new_zeroed() }
+impl ContinuationCorrectionHistory {
+    pub fn try_new() -> Option<Self> {
+        Some(Self { entries: HugeBox::try_new_zeroed()? })
     }
 }
 
@@ -248,9 +245,9 @@ impl ContinuationHistory {
     }
 }
 
-impl Default for ContinuationHistory {
-    fn default() -> Self {
-        Self { entries: HugeBox::new_zeroed() }
+impl ContinuationHistory {
+    pub fn try_new() -> Option<Self> {
+        Some(Self { entries: HugeBox::try_new_zeroed()? })
     }
 }
 
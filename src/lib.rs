@@ -5,17 +5,23 @@
 #![cfg_attr(target_arch = "wasm32", allow(dead_code, unused_imports))]
 
 mod board;
+mod book;
+mod contempt;
 mod evaluation;
 mod history;
 mod lookup;
+mod mate;
 mod misc;
 mod movepick;
 mod nnue;
 mod numa;
 mod parameters;
+mod retrograde;
 mod search;
 mod setwise;
+mod skill;
 mod stack;
+mod tb;
 mod thread;
 mod threadpool;
 mod time;
@@ -27,9 +33,6 @@ mod tools;
 #[cfg(not(target_arch = "wasm32"))]
 mod uci;
 
-#[cfg(feature = "syzygy")]
-mod tb;
-
 #[cfg(feature = "syzygy")]
 #[allow(warnings)]
 mod bindings;
@@ -38,8 +41,16 @@ mod bindings;
 pub mod wasm;
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn run(buffer: std::collections::VecDeque<String>) {
+pub fn run(mut buffer: std::collections::VecDeque<String>) {
     lookup::initialize();
     nnue::initialize();
-    uci::message_loop(buffer);
+
+    // `--net <file>` is a flag rather than a command, so take it out before the
+    // remaining arguments are executed as commands.
+    let net = buffer.iter().position(|arg| arg == "--net").and_then(|index| {
+        buffer.remove(index);
+        buffer.remove(index)
+    });
+
+    uci::message_loop(buffer, net);
 }
@@ -7,9 +7,10 @@ static mut A: [Square; 0x2000] = [Square::None; 0x2000];
 static mut B: [Square; 0x2000] = [Square::None; 0x2000];
 
 pub fn initialize() {
-    unsafe {
-        init_cuckoo();
-    }
+    // Concurrent initialization would corrupt the cuckoo tables, so it is guarded to run only once
+    static INITIALIZED: std::sync::Once = std::sync::Once::new();
+
+    INITIALIZED.call_once(|| unsafe { init_cuckoo() });
 }
 
 unsafe fn init_cuckoo() {
@@ -0,0 +1,299 @@
+//! A mate solver for `go mate N`.
+//!
+//! Unlike the main search, the solver does not prune or reduce: the attacker tries every legal
+//! move and the defender must be mated after every reply. Iterative deepening on the number of
+//! moves means the first mate found is the shortest one, and a completed iteration proves that
+//! no shorter mate exists.
+//!
+//! See [Mate Search](https://www.chessprogramming.org/Mate_Search) for more information.
+
+use std::{collections::HashMap, time::Instant};
+
+use crate::{
+    board::{Board, NullBoardObserver},
+    thread::Status,
+    time::TimeManager,
+    types::Move,
+};
+
+/// Entries kept before the table is cleared.
+const MAX_ENTRIES: usize = 1 << 22;
+
+#[derive(Copy, Clone, Default)]
+struct Entry {
+    /// The attacker has no mate within this many moves.
+    no_mate_in: u32,
+    /// The mating move of the attacker or the refutation of the defender, tried first.
+    hint: Move,
+}
+
+pub enum MateResult {
+    /// A mate in `moves` was found; no shorter mate exists.
+    Found { moves: u32, pv: Vec<Move> },
+    /// There is no mate within the requested number of moves.
+    NoMate,
+    /// The search was stopped after proving that there is no mate within `moves`.
+    Stopped { moves: u32 },
+}
+
+pub struct MateSearch<'a> {
+    time_manager: TimeManager,
+    status: &'a Status,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    table: HashMap<u64, Entry>,
+}
+
+impl<'a> MateSearch<'a> {
+    pub fn new(time_manager: TimeManager, status: &'a Status) -> Self {
+        Self {
+            time_manager,
+            status,
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            table: HashMap::new(),
+        }
+    }
+
+    /// Searches for a mate in at most `max_moves` moves by the side to move, restricted to
+    /// `searchmoves` at the root unless it is empty. Prints an `info` line per iteration.
+    pub fn solve(&mut self, board: &Board, max_moves: u32, searchmoves: &[Move]) -> MateResult {
+        let mut board = board.clone();
+
+        for moves in 1..=max_moves {
+            let found = self.attack(&mut board, moves, searchmoves);
+
+            if self.stopped {
+                return MateResult::Stopped { moves: moves - 1 };
+            }
+
+            let elapsed = self.start.elapsed();
+            let nps = (self.nodes as f64 / elapsed.as_secs_f64()) as u64;
+            let ms = elapsed.as_millis();
+            let depth = 2 * moves - 1;
+
+            if found.is_some() {
+                let pv = self.principal_variation(&mut board, moves, searchmoves);
+                let line = uci_line(&board, &pv);
+
+                println!(
+                    "info depth {depth} seldepth {depth} score mate {moves} nodes {} time {ms} nps {nps} pv {line}",
+                    self.nodes
+                );
+                return MateResult::Found { moves, pv };
+            }
+
+            println!("info depth {depth} seldepth {depth} nodes {} time {ms} nps {nps}", self.nodes);
+        }
+
+        MateResult::NoMate
+    }
+
+    /// Returns a move that mates in at most `moves` moves, with the attacker to move.
+    fn attack(&mut self, board: &mut Board, moves: u32, searchmoves: &[Move]) -> Option<Move> {
+        let entry = self.table.get(&board.hash()).copied().unwrap_or_default();
+        if moves == 0 || entry.no_mate_in >= moves {
+            return None;
+        }
+
+        // Checks come first; on the last move only checks can mate.
+        let mut candidates = Vec::new();
+        for mv in board.generate_all_moves().iter().map(|entry| entry.mv) {
+            if !searchmoves.is_empty() && !searchmoves.contains(&mv) {
+                continue;
+            }
+
+            board.make_move(mv, &mut NullBoardObserver);
+            let check = board.in_check();
+            board.undo_move(mv);
+
+            if check || moves > 1 {
+                let priority = 2 * (mv == entry.hint) as i32 + check as i32;
+                candidates.push((mv, priority));
+            }
+        }
+        candidates.sort_by_key(|&(_, priority)| std::cmp::Reverse(priority));
+
+        for (mv, _) in candidates {
+            board.make_move(mv, &mut NullBoardObserver);
+            let mated = self.defend(board, moves);
+            board.undo_move(mv);
+
+            if self.stopped {
+                return None;
+            }
+
+            if mated {
+                self.store(board.hash(), Entry { hint: mv, ..entry });
+                return Some(mv);
+            }
+        }
+
+        // A bound found with only some of the root moves does not hold for the position
+        if searchmoves.is_empty() {
+            self.store(board.hash(), Entry { no_mate_in: moves, ..entry });
+        }
+        None
+    }
+
+    /// Returns `true` if the defender to move is mated, with the attacker having `moves` moves
+    /// including the one just played.
+    fn defend(&mut self, board: &mut Board, moves: u32) -> bool {
+        self.nodes += 1;
+        if self.nodes & 1023 == 0 && self.should_stop() {
+            self.stopped = true;
+            return false;
+        }
+
+        let mut replies = board.generate_all_moves().iter().map(|entry| entry.mv).collect::<Vec<_>>();
+        if replies.is_empty() {
+            return board.in_check();
+        }
+
+        if moves == 1 {
+            return false;
+        }
+
+        // The refutation found last time is validated like any other table move.
+        let hint = self.table.get(&board.hash()).map_or(Move::NULL, |entry| entry.hint);
+        if hint.is_present()
+            && board.is_legal(hint)
+            && let Some(index) = replies.iter().position(|&mv| mv == hint)
+        {
+            replies.swap(0, index);
+        }
+
+        for mv in replies {
+            board.make_move(mv, &mut NullBoardObserver);
+            let escaped = self.attack(board, moves - 1, &[]).is_none();
+            board.undo_move(mv);
+
+            if escaped {
+                if !self.stopped {
+                    let entry = self.table.get(&board.hash()).copied().unwrap_or_default();
+                    self.store(board.hash(), Entry { hint: mv, ..entry });
+                }
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Follows the fastest mate for the attacker and the longest resistance for the defender.
+    fn principal_variation(&mut self, board: &mut Board, moves: u32, searchmoves: &[Move]) -> Vec<Move> {
+        let Some((mv, shortest)) = (1..=moves).find_map(|n| Some((self.attack(board, n, searchmoves)?, n))) else {
+            return Vec::new();
+        };
+
+        let mut pv = vec![mv];
+        board.make_move(mv, &mut NullBoardObserver);
+
+        let replies = board.generate_all_moves().iter().map(|entry| entry.mv).collect::<Vec<_>>();
+        let longest = replies
+            .into_iter()
+            .map(|reply| {
+                board.make_move(reply, &mut NullBoardObserver);
+                let length = (1..shortest).find(|&n| self.attack(board, n, &[]).is_some()).unwrap_or(shortest);
+                board.undo_move(reply);
+                (reply, length)
+            })
+            .max_by_key(|&(_, length)| length);
+
+        if let Some((reply, _)) = longest {
+            pv.push(reply);
+            board.make_move(reply, &mut NullBoardObserver);
+            pv.extend(self.principal_variation(board, shortest - 1, &[]));
+            board.undo_move(reply);
+        }
+
+        board.undo_move(mv);
+        pv
+    }
+
+    fn store(&mut self, hash: u64, entry: Entry) {
+        if self.table.len() >= MAX_ENTRIES {
+            self.table.clear();
+        }
+        self.table.insert(hash, entry);
+    }
+
+    pub const fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    fn should_stop(&self) -> bool {
+        self.status.get() == Status::STOPPED
+            || self.time_manager.limits().nodes.is_some_and(|maximum| self.nodes >= maximum)
+            || self.time_manager.hard_bound_passed()
+    }
+}
+
+/// Formats the moves of `pv`, each in the position it is played in.
+fn uci_line(board: &Board, pv: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut line = Vec::new();
+
+    for &mv in pv {
+        line.push(mv.to_uci(&board));
+        board.make_move(mv, &mut NullBoardObserver);
+    }
+
+    line.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lookup,
+        time::{Clock, Limits},
+    };
+
+    fn solve_with(fen: &str, moves: u32, limits: Limits) -> MateResult {
+        lookup::initialize();
+
+        let board = Board::from_fen(fen).unwrap();
+        let status = Status::default();
+        MateSearch::new(TimeManager::new(limits, board.fullmove_number(), 0), &status).solve(&board, moves, &[])
+    }
+
+    fn solve(fen: &str, moves: u32) -> MateResult {
+        solve_with(fen, moves, Limits::default())
+    }
+
+    #[test]
+    fn finds_the_shortest_mate() {
+        let MateResult::Found { moves, pv } =
+            solve("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 4)
+        else {
+            panic!("mate not found");
+        };
+
+        let board = Board::from_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1").unwrap();
+        assert_eq!(moves, 2);
+        assert_eq!(uci_line(&board, &pv), "d5f6 g7f6 c4f7");
+    }
+
+    #[test]
+    fn proves_there_is_no_mate() {
+        assert!(matches!(solve("8/8/8/8/8/5k2/8/4K2R w K - 0 1", 3), MateResult::NoMate));
+    }
+
+    #[test]
+    fn stops_on_the_clock() {
+        let clock = Clock { time: 200, increment: 0, moves_to_go: None };
+        let start = Instant::now();
+
+        let result = solve_with(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            8,
+            Limits { clock: Some(clock), ..Default::default() },
+        );
+
+        assert!(matches!(result, MateResult::Stopped { .. }));
+        assert!(start.elapsed().as_millis() < 2000);
+    }
+}
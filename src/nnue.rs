@@ -1,8 +1,14 @@
 mod accumulator;
+mod format;
 
-pub use accumulator::threats::initialize;
+pub use format::Header;
 
-use std::sync::Arc;
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::Path,
+    sync::{Arc, RwLock},
+};
 
 use crate::{
     board::{Board, BoardObserver},
@@ -366,19 +372,247 @@ pub struct Parameters {
     l3_biases: Aligned<[f32; OUTPUT_BUCKETS]>,
 }
 
+/// The network loaded at runtime through `EvalFile`, or `None` to use the embedded one.
+static LOADED: RwLock<Option<LoadedNetwork>> = RwLock::new(None);
+
+struct LoadedNetwork {
+    path: String,
+    header: Header,
+    parameters: Arc<Parameters>,
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    /// The file could not be read or written.
+    Io(std::io::Error),
+    /// The file does not start with a network header.
+    MissingHeader,
+    /// The file already starts with a network header.
+    AlreadyConverted,
+    /// The header was written by an unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// The network was trained for a different architecture or quantization.
+    ArchitectureMismatch(Header),
+    /// The file does not have the size of the network architecture this binary was built for.
+    InvalidSize { expected: usize, found: u64 },
+    /// The parameters do not match the content hash in the header.
+    ChecksumMismatch { expected: u64, found: u64 },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::MissingHeader => write!(f, "missing network header (convert headerless files with `convertnet`)"),
+            Self::AlreadyConverted => write!(f, "the file already has a network header"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}, expected {}", format::VERSION)
+            }
+            Self::ArchitectureMismatch(header) => {
+                write!(f, "architecture {} does not match {}", describe(header), describe(&Header::current(0)))
+            }
+            Self::InvalidSize { expected, found } => write!(f, "expected {expected} bytes, found {found}"),
+            Self::ChecksumMismatch { expected, found } => {
+                write!(f, "content hash {found:016x} does not match {expected:016x}")
+            }
+        }
+    }
+}
+
+fn describe(header: &Header) -> String {
+    format!(
+        "{}->{}->{}->1 ({} input, {} output buckets, quant {}/{}, scale {})",
+        header.l1_size,
+        header.l2_size,
+        header.l3_size,
+        header.input_buckets,
+        header.output_buckets,
+        header.ft_quant,
+        header.l1_quant,
+        header.network_scale
+    )
+}
+
+impl Header {
+    /// Returns the header describing the architecture this binary was built for.
+    fn current(content_hash: u64) -> Self {
+        Self {
+            version: format::VERSION,
+            l1_size: L1_SIZE as u32,
+            l2_size: L2_SIZE as u32,
+            l3_size: L3_SIZE as u32,
+            input_buckets: INPUT_BUCKETS as u32,
+            output_buckets: OUTPUT_BUCKETS as u32,
+            ft_quant: FT_QUANT,
+            l1_quant: L1_QUANT,
+            network_scale: NETWORK_SCALE,
+            content_hash,
+        }
+    }
+
+    /// Checks that the header belongs to a network this binary can evaluate.
+    fn check_compatible(&self) -> Result<(), NetworkError> {
+        if self.version != format::VERSION {
+            return Err(NetworkError::UnsupportedVersion(self.version));
+        }
+
+        if *self != Self::current(self.content_hash) {
+            return Err(NetworkError::ArchitectureMismatch(*self));
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates the architecture dimensions of the embedded network. Its checksum is verified by
+/// the build script, and builds from a headerless network file are accepted as they are.
+pub fn initialize() {
+    accumulator::threats::initialize();
+
+    if let Some(header) = Parameters::embedded_header()
+        && let Err(e) = header.check_compatible()
+    {
+        panic!("The embedded network is invalid: {e}");
+    }
+}
+
+/// Makes the network at `path` the source for every subsequently allocated replica,
+/// or restores the embedded network if `path` is empty. On failure the embedded
+/// network is restored as well.
+///
+/// Existing replicas are not affected; see `NumaReplicated::refresh`.
+pub fn set_network(path: &str) -> Result<(), NetworkError> {
+    let loaded = if path.is_empty() { Ok(None) } else { Parameters::load(path).map(Some) };
+
+    *LOADED.write().unwrap() = match &loaded {
+        Ok(Some((header, parameters))) => Some(LoadedNetwork {
+            path: path.to_string(),
+            header: *header,
+            parameters: parameters.clone(),
+        }),
+        _ => None,
+    };
+
+    loaded.map(|_| ())
+}
+
+/// Describes the active network for `netinfo`.
+pub fn network_info() -> Vec<String> {
+    let (source, header) = match &*LOADED.read().unwrap() {
+        Some(loaded) => (format!("file '{}'", loaded.path), Some(loaded.header)),
+        None => ("embedded".to_string(), Parameters::embedded_header()),
+    };
+
+    let mut lines = vec![format!("Source:       {source}")];
+
+    match header {
+        Some(header) => {
+            lines.push(format!("Version:      {}", header.version));
+            lines.push(format!("Architecture: {}", describe(&header)));
+            lines.push(format!("Content hash: {:016x}", header.content_hash));
+        }
+        None => {
+            lines.push("Version:      none (headerless)".to_string());
+            lines.push(format!("Architecture: {}", describe(&Header::current(0))));
+            lines.push(format!("Content hash: {:016x}", format::content_hash(Parameters::embedded().as_bytes())));
+        }
+    }
+
+    lines
+}
+
+/// Prepends a header for this binary's architecture to a headerless network file.
+pub fn convert(input: &str, output: &str) -> Result<Header, NetworkError> {
+    let bytes = std::fs::read(input).map_err(NetworkError::Io)?;
+
+    if Header::is_present(&bytes) {
+        return Err(NetworkError::AlreadyConverted);
+    }
+
+    let expected = std::mem::size_of::<Parameters>();
+    if bytes.len() != expected {
+        return Err(NetworkError::InvalidSize { expected, found: bytes.len() as u64 });
+    }
+
+    let header = Header::current(format::content_hash(&bytes));
+
+    let mut file = std::fs::File::create(output).map_err(NetworkError::Io)?;
+    file.write_all(&header.to_bytes()).and_then(|()| file.write_all(&bytes)).map_err(NetworkError::Io)?;
+
+    Ok(header)
+}
+
 impl Parameters {
     fn embedded() -> &'static Self {
         static EMBEDDED: Parameters = unsafe { std::mem::transmute(*include_bytes!(env!("MODEL"))) };
         &EMBEDDED
     }
 
+    /// The header of the embedded network, stripped off by the build script,
+    /// or `None` if the network was built from a headerless file.
+    fn embedded_header() -> Option<Header> {
+        Header::parse(include_bytes!(env!("MODEL_HEADER")))
+    }
+
+    /// Returns the network new replicas are copied from.
+    fn source() -> Option<Arc<Self>> {
+        LOADED.read().unwrap().as_ref().map(|loaded| loaded.parameters.clone())
+    }
+
+    const fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `Parameters` consists of plain integer and float arrays without padding.
+        unsafe { std::slice::from_raw_parts((self as *const Self).cast::<u8>(), std::mem::size_of::<Self>()) }
+    }
+
+    fn verify(&self, header: &Header) -> Result<(), NetworkError> {
+        let found = format::content_hash(self.as_bytes());
+        match found == header.content_hash {
+            true => Ok(()),
+            false => Err(NetworkError::ChecksumMismatch { expected: header.content_hash, found }),
+        }
+    }
+
+    /// Reads and validates a network file, placing the parameters directly into an aligned allocation.
+    fn load(path: impl AsRef<Path>) -> Result<(Header, Arc<Self>), NetworkError> {
+        let mut file = std::fs::File::open(path).map_err(NetworkError::Io)?;
+
+        let mut bytes = [0; format::HEADER_SIZE];
+        file.read_exact(&mut bytes).map_err(|_| NetworkError::MissingHeader)?;
+
+        let header = Header::parse(&bytes).ok_or(NetworkError::MissingHeader)?;
+        header.check_compatible()?;
+
+        let expected = format::HEADER_SIZE + std::mem::size_of::<Self>();
+        let found = file.metadata().map_err(NetworkError::Io)?.len();
+        if found != expected as u64 {
+            return Err(NetworkError::InvalidSize { expected, found });
+        }
+
+        let mut boxed = Box::<Self>::new_uninit();
+        debug_assert!(boxed.as_ptr().cast::<u8>().align_offset(std::mem::align_of::<Self>()) == 0);
+
+        // SAFETY: the buffer spans exactly one `Parameters`, and every bit pattern
+        // is a valid value for its integer and float fields.
+        let parameters = unsafe {
+            let bytes = std::slice::from_raw_parts_mut(boxed.as_mut_ptr().cast::<u8>(), std::mem::size_of::<Self>());
+            file.read_exact(bytes).map_err(NetworkError::Io)?;
+            boxed.assume_init()
+        };
+
+        parameters.verify(&header)?;
+        Ok((header, Arc::from(parameters)))
+    }
+
     fn allocate_owned() -> Arc<Self> {
+        let source = Self::source();
+        let source = source.as_deref().unwrap_or_else(|| Self::embedded());
+
         let mut boxed = Box::<std::mem::MaybeUninit<Self>>::new(std::mem::MaybeUninit::uninit());
         let ptr = boxed.as_mut_ptr();
         std::mem::forget(boxed);
 
         unsafe {
-            std::ptr::copy_nonoverlapping(Self::embedded() as *const Self, ptr, 1);
+            std::ptr::copy_nonoverlapping(source as *const Self, ptr, 1);
             Arc::from(Box::from_raw(ptr))
         }
     }
@@ -422,7 +656,10 @@ impl NumaReplicable for ParametersHandle {
     }
 
     fn allocate_shared() -> Option<Arc<Self>> {
-        Arc::new(Self::embedded()).into()
+        match Parameters::source() {
+            Some(parameters) => Arc::new(Self::owned(parameters)).into(),
+            None => Arc::new(Self::embedded()).into(),
+        }
     }
 }
 
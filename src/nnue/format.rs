@@ -0,0 +1,101 @@
+//! The network container format: a 64-byte little-endian header followed by the raw
+//! `Parameters` block.
+//!
+//! | Offset | Size | Field                                    |
+//! |--------|------|------------------------------------------|
+//! | 0      | 8    | Magic (`RECKLESS`)                       |
+//! | 8      | 4    | Format version                           |
+//! | 12     | 20   | L1, L2, L3, input and output bucket size |
+//! | 32     | 12   | FT quant, L1 quant, network scale        |
+//! | 44     | 4    | Reserved                                 |
+//! | 48     | 8    | Content hash of the parameter block      |
+//! | 56     | 8    | Reserved                                 |
+//!
+//! The header is exactly one cache line, so the parameters that follow it keep their alignment.
+//!
+//! This file is also included by the build script and must not depend on the rest of the crate.
+
+pub const MAGIC: [u8; 8] = *b"RECKLESS";
+pub const VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    pub l1_size: u32,
+    pub l2_size: u32,
+    pub l3_size: u32,
+    pub input_buckets: u32,
+    pub output_buckets: u32,
+    pub ft_quant: i32,
+    pub l1_quant: i32,
+    pub network_scale: i32,
+    pub content_hash: u64,
+}
+
+impl Header {
+    /// Returns `true` if `bytes` starts with the container magic.
+    pub fn is_present(bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC)
+    }
+
+    /// Parses the header at the start of `bytes`, or returns `None` if the magic is missing.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE || !Self::is_present(bytes) {
+            return None;
+        }
+
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let i32_at = |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        Some(Self {
+            version: u32_at(8),
+            l1_size: u32_at(12),
+            l2_size: u32_at(16),
+            l3_size: u32_at(20),
+            input_buckets: u32_at(24),
+            output_buckets: u32_at(28),
+            ft_quant: i32_at(32),
+            l1_quant: i32_at(36),
+            network_scale: i32_at(40),
+            content_hash: u64::from_le_bytes(bytes[48..56].try_into().unwrap()),
+        })
+    }
+
+    pub fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..8].copy_from_slice(&MAGIC);
+
+        let fields = [
+            self.version,
+            self.l1_size,
+            self.l2_size,
+            self.l3_size,
+            self.input_buckets,
+            self.output_buckets,
+            self.ft_quant as u32,
+            self.l1_quant as u32,
+            self.network_scale as u32,
+        ];
+
+        for (index, field) in fields.iter().enumerate() {
+            bytes[8 + 4 * index..12 + 4 * index].copy_from_slice(&field.to_le_bytes());
+        }
+
+        bytes[48..56].copy_from_slice(&self.content_hash.to_le_bytes());
+        bytes
+    }
+}
+
+/// FNV-1a over the little-endian 64-bit words of the parameter block; a trailing
+/// partial word is zero-padded.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+    bytes.chunks(8).fold(OFFSET, |hash, chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        (hash ^ u64::from_le_bytes(word)).wrapping_mul(PRIME)
+    })
+}
@@ -340,6 +340,11 @@ impl<T: NumaReplicable> NumaReplicated<T> {
         self.instances.read().unwrap().clone()
     }
 
+    /// Discards the current replicas and allocates new ones on every node.
+    pub fn refresh(&self) {
+        self.replicate_instances();
+    }
+
     fn replicate_instances(&self) {
         let cfg = self.ctx.get_numa_config();
         let mut instances = Vec::<Arc<T>>::new();
@@ -0,0 +1,964 @@
+//! Endgame tablebases generated by retrograde analysis.
+//!
+//! Builds without Syzygy support, such as `--no-default-features` and wasm, have no other source
+//! of perfect endgame play. The tables built here cover chosen material signatures of up to
+//! five pieces and store the distance to mate of every position, so that the search can convert
+//! endings like KBNK that are far beyond its horizon.
+//!
+//! Generation works backwards from the mates: a position with a move into a lost position is
+//! won, and a position whose moves all lead into won positions is lost. Captures and promotions
+//! leave the table, so their values come from the tables of the resulting material, which are
+//! generated first. En passant and castling are not modelled, and positions with either are
+//! never probed.
+//!
+//! See [Retrograde Analysis](https://www.chessprogramming.org/Retrograde_Analysis) for more information.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Read, Write},
+    ops::Range,
+    path::Path,
+    sync::{
+        RwLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    board::Board,
+    lookup::attacks,
+    types::{Bitboard, Color, Piece, PieceType, Rank, Square},
+};
+
+/// The largest number of pieces, kings included, of a table.
+pub const MAX_PIECES: usize = 5;
+
+/// The longest distance to mate, in plies, that is stored exactly.
+const MAX_DTM: usize = 252;
+
+/// The stored value of a win longer than `MAX_DTM`, which is not probed.
+const LONG_WIN: u8 = MAX_DTM as u8 + 2;
+
+/// The stored value of a loss longer than `MAX_DTM`, which is not probed.
+const LONG_LOSS: u8 = MAX_DTM as u8 + 3;
+
+/// Marks the move count of an index that is not a canonical legal position.
+const INVALID: u8 = u8::MAX;
+
+/// Marks the move count of a position that is known to be won.
+const PENDING_WIN: u8 = u8::MAX - 1;
+
+const MAGIC: &[u8; 4] = b"RTB2";
+
+/// The squares a pawn can stand on.
+const PAWN_SQUARES: u64 = 0x00FF_FFFF_FFFF_FF00;
+
+const PROMOTIONS: [PieceType; 4] = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+
+static TABLES: RwLock<Vec<Table>> = RwLock::new(Vec::new());
+
+/// The piece count of the largest loaded table, or zero if none are loaded.
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the calls to `load`, so that only the latest one installs its tables.
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The value of a position for the side to move, with the distance to mate in plies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win(usize),
+    Loss(usize),
+    Draw,
+}
+
+impl Outcome {
+    /// Decodes a stored value, or returns `None` for a win or a loss too long to be stored.
+    const fn from_value(value: u8) -> Option<Self> {
+        match value {
+            LONG_WIN | LONG_LOSS => None,
+            _ => Some(Self::at_least(value)),
+        }
+    }
+
+    /// Decodes a stored value: zero for a draw, otherwise the distance to mate plus one.
+    /// Wins are an odd number of plies from mate, losses an even number. `LONG_WIN` and
+    /// `LONG_LOSS` decode to the shortest distance they stand for.
+    const fn at_least(value: u8) -> Self {
+        match value {
+            0 => Self::Draw,
+            _ if value.is_multiple_of(2) => Self::Win(value as usize - 1),
+            _ => Self::Loss(value as usize - 1),
+        }
+    }
+}
+
+/// Loads the tables of the comma separated `signatures` (for example `KQKR, KBNK`) together with
+/// the tables they depend on, replacing the loaded ones. Tables found in the `cache` directory
+/// are read from it, the others are generated and written to it. Returns the number of tables.
+///
+/// Generation can take a while, so this may run on its own thread while the engine keeps
+/// searching with the tables loaded before. A call overtaken by a later one installs nothing.
+pub fn load(signatures: &str, cache: &str) -> Result<usize, String> {
+    let request = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let mut materials = Vec::new();
+    for signature in signatures.split(',').map(str::trim).filter(|signature| !signature.is_empty()) {
+        match Material::parse(signature) {
+            Some(material) => materials.push(material),
+            None => return Err(format!("Invalid endgame table signature '{signature}'")),
+        }
+    }
+
+    let cache = (!cache.is_empty()).then(|| Path::new(cache));
+
+    let mut tables = Vec::new();
+    for material in &materials {
+        build(material, &mut tables, cache).map_err(|e| format!("Failed to build endgame table {material}: {e}"))?;
+    }
+
+    let count = tables.len();
+    let largest = tables.iter().map(|table| table.layout.material.0.len()).max().unwrap_or(0);
+
+    let mut loaded = TABLES.write().unwrap();
+    if REQUESTS.load(Ordering::Relaxed) != request {
+        return Err(format!("Endgame tables for '{signatures}' replaced by a later request"));
+    }
+
+    *loaded = tables;
+    LARGEST.store(largest, Ordering::Relaxed);
+    Ok(count)
+}
+
+/// Probes the loaded tables, returning `None` if no table covers the position or its distance
+/// to mate is too long to be stored.
+pub fn probe(board: &Board) -> Option<Outcome> {
+    if board.occupancies().popcount() > LARGEST.load(Ordering::Relaxed)
+        || board.castling().raw() != 0
+        || board.en_passant() != Square::None
+    {
+        return None;
+    }
+
+    let position = Position::from_board(board);
+    lookup(&TABLES.read().unwrap(), &position).and_then(Outcome::from_value)
+}
+
+/// Reads or generates the table of `material` after the tables it depends on.
+fn build(material: &Material, tables: &mut Vec<Table>, cache: Option<&Path>) -> io::Result<()> {
+    let flipped = material.flipped();
+    if material.is_insufficient() || tables.iter().any(|table| [material, &flipped].contains(&&table.layout.material)) {
+        return Ok(());
+    }
+
+    for successor in material.successors() {
+        build(&successor, tables, cache)?;
+    }
+
+    let table = match cache.map(|directory| directory.join(format!("{material}.rtb"))) {
+        Some(path) if path.exists() => Table::read(material.clone(), &path)?,
+        Some(path) => {
+            let table = generate(material.clone(), tables);
+            table.write(&path)?;
+            table
+        }
+        None => generate(material.clone(), tables),
+    };
+
+    tables.push(table);
+    Ok(())
+}
+
+/// Returns the stored value of `position`, which need not be normalized.
+fn lookup(tables: &[Table], position: &Position) -> Option<u8> {
+    let position = position.normalized();
+    let material = position.material();
+
+    if material.is_insufficient() {
+        return Some(0);
+    }
+
+    if let Some(table) = tables.iter().find(|table| table.layout.material == material) {
+        return Some(table.probe(&position));
+    }
+
+    let flipped = position.flipped();
+    let material = flipped.material();
+    tables.iter().find(|table| table.layout.material == material).map(|table| table.probe(&flipped))
+}
+
+/// Generates the table of `material`; the tables of its successors must be in `tables`.
+fn generate(material: Material, tables: &[Table]) -> Table {
+    let layout = Layout::new(material);
+
+    let mut values = vec![0u8; layout.size];
+    // Unresolved children of each position, counting every drawing exit as one child
+    let mut counts = vec![0u8; layout.size];
+    // The earliest level at which a position can be resolved
+    let mut levels = vec![0u16; layout.size];
+    let mut queues = Vec::new();
+
+    let mut children = Vec::new();
+    for index in 0..layout.size {
+        let position = layout.decode(index);
+        if !position.is_legal() || layout.index(&position) != index {
+            counts[index] = INVALID;
+            continue;
+        }
+
+        let (mut moves, mut exits, mut win, mut floor) = (0, 0, usize::MAX, 0);
+        children.clear();
+
+        for_each_move(&position, |child| {
+            moves += 1;
+
+            if child.len == position.len && child.pieces == position.pieces {
+                children.push(layout.index(&child));
+                return;
+            }
+
+            match lookup(tables, &child).map(Outcome::at_least) {
+                Some(Outcome::Loss(dtm)) => win = win.min(dtm + 1),
+                Some(Outcome::Win(dtm)) => floor = floor.max(dtm + 1),
+                _ => exits = 1,
+            }
+        });
+
+        if moves == 0 {
+            if position.in_check() {
+                enqueue(&mut queues, 0, index);
+            } else {
+                // Stalemate: never resolved, so it stays a draw
+                counts[index] = 1;
+            }
+            continue;
+        }
+
+        if win != usize::MAX {
+            counts[index] = PENDING_WIN;
+            levels[index] = win as u16;
+            enqueue(&mut queues, win, index);
+            continue;
+        }
+
+        children.sort_unstable();
+        children.dedup();
+
+        counts[index] = (children.len() + exits) as u8;
+        levels[index] = floor as u16;
+
+        if counts[index] == 0 {
+            enqueue(&mut queues, floor, index);
+        }
+    }
+
+    let mut parents = Vec::new();
+    let mut level = 0;
+    while level < queues.len() {
+        for index in std::mem::take(&mut queues[level]) {
+            let index = index as usize;
+            if values[index] != 0 {
+                continue;
+            }
+
+            values[index] = if level <= MAX_DTM {
+                level as u8 + 1
+            } else if level % 2 == 1 {
+                LONG_WIN
+            } else {
+                LONG_LOSS
+            };
+
+            parents.clear();
+            for_each_unmove(&layout.decode(index), |parent| parents.push(layout.index(&parent)));
+            parents.sort_unstable();
+            parents.dedup();
+
+            for &parent in &parents {
+                if values[parent] != 0 || counts[parent] == INVALID {
+                    continue;
+                }
+
+                if level % 2 == 0 {
+                    // Moving into a lost position wins
+                    if counts[parent] != PENDING_WIN || levels[parent] as usize > level + 1 {
+                        counts[parent] = PENDING_WIN;
+                        levels[parent] = level as u16 + 1;
+                        enqueue(&mut queues, level + 1, parent);
+                    }
+                } else if counts[parent] != PENDING_WIN {
+                    counts[parent] -= 1;
+
+                    // Every move leads into a won position
+                    let next = (level + 1).max(levels[parent] as usize);
+                    if counts[parent] == 0 {
+                        enqueue(&mut queues, next, parent);
+                    }
+                }
+            }
+        }
+
+        level += 1;
+    }
+
+    Table { layout, values }
+}
+
+/// Adds a position to the queue of `level`. Levels have no upper bound, so that wins too long
+/// to be stored still resolve their parents.
+fn enqueue(queues: &mut Vec<Vec<u32>>, level: usize, index: usize) {
+    if queues.len() <= level {
+        queues.resize_with(level + 1, Vec::new);
+    }
+    queues[level].push(index as u32);
+}
+
+/// Calls `f` with every position reached by a legal move. The pieces of a position reached by
+/// a capture or a promotion are no longer in material order.
+fn for_each_move(position: &Position, mut f: impl FnMut(Position)) {
+    let stm = position.stm;
+    let occupancy = position.occupancy();
+    let own = position.color_occupancy(stm);
+
+    for i in 0..position.len {
+        let piece = position.pieces[i];
+        if piece.color() != stm {
+            continue;
+        }
+
+        let from = Square::new(position.squares[i]);
+        let is_pawn = piece.piece_type() == PieceType::Pawn;
+
+        let targets = if is_pawn {
+            pawn_pushes(from, stm, occupancy) | (attacks(piece, from, occupancy) & position.color_occupancy(!stm))
+        } else {
+            attacks(piece, from, occupancy) & !own
+        };
+
+        for to in targets {
+            let mut child = *position;
+            child.squares[i] = to as u8;
+            child.stm = !stm;
+
+            if let Some(captured) = (0..child.len).find(|&j| j != i && child.squares[j] == to as u8) {
+                child.remove(captured);
+            }
+
+            if child.attacked(child.king(stm), !stm) {
+                continue;
+            }
+
+            if is_pawn && matches!(to.rank(), Rank::R1 | Rank::R8) {
+                let moved = (0..child.len).find(|&j| child.squares[j] == to as u8).unwrap();
+                for piece_type in PROMOTIONS {
+                    let mut promoted = child;
+                    promoted.pieces[moved] = Piece::new(stm, piece_type);
+                    f(promoted);
+                }
+            } else {
+                f(child);
+            }
+        }
+    }
+}
+
+/// Calls `f` with every legal position, with the other side to move, from which a move that
+/// neither captures nor promotes reaches `position`.
+fn for_each_unmove(position: &Position, mut f: impl FnMut(Position)) {
+    let mover = !position.stm;
+    let occupancy = position.occupancy();
+
+    for i in 0..position.len {
+        let piece = position.pieces[i];
+        if piece.color() != mover {
+            continue;
+        }
+
+        let to = Square::new(position.squares[i]);
+        let origins = if piece.piece_type() == PieceType::Pawn {
+            pawn_origins(to, mover, occupancy)
+        } else {
+            attacks(piece, to, occupancy) & !occupancy
+        };
+
+        for from in origins {
+            let mut parent = *position;
+            parent.squares[i] = from as u8;
+            parent.stm = mover;
+
+            if parent.is_legal() {
+                f(parent);
+            }
+        }
+    }
+}
+
+fn pawn_pushes(from: Square, color: Color, occupancy: Bitboard) -> Bitboard {
+    let single = from.shift(Square::UP[color]);
+    if occupancy.contains(single) {
+        return Bitboard(0);
+    }
+
+    let mut pushes = single.to_bb();
+    if from.relative_to(color).rank() == Rank::R2 {
+        let double = single.shift(Square::UP[color]);
+        if !occupancy.contains(double) {
+            pushes |= double.to_bb();
+        }
+    }
+    pushes
+}
+
+fn pawn_origins(to: Square, color: Color, occupancy: Bitboard) -> Bitboard {
+    let rank = to.relative_to(color).rank();
+    if rank < Rank::R3 {
+        return Bitboard(0);
+    }
+
+    let single = to.shift(-Square::UP[color]);
+    if occupancy.contains(single) {
+        return Bitboard(0);
+    }
+
+    let mut origins = single.to_bb();
+    if rank == Rank::R4 {
+        let double = single.shift(-Square::UP[color]);
+        if !occupancy.contains(double) {
+            origins |= double.to_bb();
+        }
+    }
+    origins
+}
+
+/// Sort key of the material order: the kings, then the white and the black pieces from the
+/// most to the least valuable.
+const fn order(piece: Piece) -> u8 {
+    match piece.piece_type() {
+        PieceType::King => piece.color() as u8,
+        piece_type => 2 + 8 * piece.color() as u8 + (PieceType::King as u8 - piece_type as u8),
+    }
+}
+
+/// The pieces of a table in material order. Identical pieces are adjacent.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Material(Vec<Piece>);
+
+impl Material {
+    /// Parses a signature like `KQKR`: the white pieces starting with the king, then the black ones.
+    fn parse(signature: &str) -> Option<Self> {
+        let signature = signature.to_ascii_uppercase();
+        if !signature.starts_with('K') {
+            return None;
+        }
+
+        let black = signature[1..].find('K')? + 1;
+
+        let mut pieces = Vec::new();
+        for (index, c) in signature.char_indices() {
+            let color = if index < black { Color::White } else { Color::Black };
+            pieces.push(Piece::new(color, Piece::try_from(c).ok()?.piece_type()));
+        }
+
+        let kings = pieces.iter().filter(|piece| piece.piece_type() == PieceType::King).count();
+        if kings != 2 || pieces.len() > MAX_PIECES {
+            return None;
+        }
+
+        Some(Self::sorted(pieces))
+    }
+
+    fn sorted(mut pieces: Vec<Piece>) -> Self {
+        pieces.sort_by_key(|&piece| order(piece));
+        Self(pieces)
+    }
+
+    fn flipped(&self) -> Self {
+        Self::sorted(self.0.iter().map(|piece| Piece::new(!piece.color(), piece.piece_type())).collect())
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.0.iter().any(|piece| piece.piece_type() == PieceType::Pawn)
+    }
+
+    /// Neither side can force mate: bare kings or a single minor piece.
+    fn is_insufficient(&self) -> bool {
+        match self.0[2..] {
+            [] => true,
+            [piece] => matches!(piece.piece_type(), PieceType::Knight | PieceType::Bishop),
+            _ => false,
+        }
+    }
+
+    /// The materials that a capture or a promotion leads to.
+    fn successors(&self) -> Vec<Self> {
+        let mut successors = Vec::new();
+
+        for i in 2..self.0.len() {
+            let mut captured = self.0.clone();
+            captured.remove(i);
+            successors.push(Self::sorted(captured));
+
+            let color = self.0[i].color();
+            if self.0[i].piece_type() != PieceType::Pawn {
+                continue;
+            }
+
+            for piece_type in PROMOTIONS {
+                let mut promoted = self.0.clone();
+                promoted[i] = Piece::new(color, piece_type);
+
+                for j in (2..self.0.len()).filter(|&j| self.0[j].color() != color) {
+                    let mut captured = promoted.clone();
+                    captured.remove(j);
+                    successors.push(Self::sorted(captured));
+                }
+
+                successors.push(Self::sorted(promoted));
+            }
+        }
+
+        successors
+    }
+}
+
+impl Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for color in [Color::White, Color::Black] {
+            for piece in self.0.iter().filter(|piece| piece.color() == color) {
+                write!(f, "{}", Piece::new(Color::White, piece.piece_type()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A position of a table, with the pieces in material order once normalized.
+#[derive(Copy, Clone)]
+struct Position {
+    pieces: [Piece; MAX_PIECES],
+    squares: [u8; MAX_PIECES],
+    len: usize,
+    stm: Color,
+}
+
+impl Position {
+    fn from_board(board: &Board) -> Self {
+        let mut position = Self {
+            pieces: [Piece::None; MAX_PIECES],
+            squares: [0; MAX_PIECES],
+            len: 0,
+            stm: board.side_to_move(),
+        };
+
+        for square in board.occupancies() {
+            position.pieces[position.len] = board.piece_on(square);
+            position.squares[position.len] = square as u8;
+            position.len += 1;
+        }
+
+        position.normalized()
+    }
+
+    /// Puts the pieces in material order, and identical pieces in square order.
+    fn normalized(&self) -> Self {
+        let mut pairs = [(0, 0, Piece::None); MAX_PIECES];
+        for (pair, (&piece, &square)) in pairs.iter_mut().zip(self.pieces.iter().zip(&self.squares)).take(self.len) {
+            *pair = (order(piece), square, piece);
+        }
+        pairs[..self.len].sort_unstable_by_key(|&(order, square, _)| (order, square));
+
+        let mut position = *self;
+        for (i, &(_, square, piece)) in pairs[..self.len].iter().enumerate() {
+            position.pieces[i] = piece;
+            position.squares[i] = square;
+        }
+        position
+    }
+
+    /// The same position with the colors swapped, normalized.
+    fn flipped(&self) -> Self {
+        let mut position = *self;
+        for i in 0..self.len {
+            position.pieces[i] = Piece::new(!self.pieces[i].color(), self.pieces[i].piece_type());
+            position.squares[i] ^= 56;
+        }
+        position.stm = !self.stm;
+        position.normalized()
+    }
+
+    fn material(&self) -> Material {
+        Material(self.pieces[..self.len].to_vec())
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.pieces.copy_within(index + 1..self.len, index);
+        self.squares.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+    }
+
+    fn occupancy(&self) -> Bitboard {
+        Bitboard(self.squares[..self.len].iter().fold(0, |bb, &square| bb | 1 << square))
+    }
+
+    fn color_occupancy(&self, color: Color) -> Bitboard {
+        let squares = (0..self.len).filter(|&i| self.pieces[i].color() == color).map(|i| self.squares[i]);
+        Bitboard(squares.fold(0, |bb, square| bb | 1 << square))
+    }
+
+    fn king(&self, color: Color) -> u8 {
+        let king = Piece::new(color, PieceType::King);
+        self.squares[(0..self.len).find(|&i| self.pieces[i] == king).unwrap()]
+    }
+
+    fn attacked(&self, square: u8, by: Color) -> bool {
+        let occupancy = self.occupancy();
+        (0..self.len).any(|i| {
+            self.pieces[i].color() == by
+                && attacks(self.pieces[i], Square::new(self.squares[i]), occupancy).contains(Square::new(square))
+        })
+    }
+
+    fn in_check(&self) -> bool {
+        self.attacked(self.king(self.stm), !self.stm)
+    }
+
+    /// No two pieces share a square, no pawn stands on a back rank and the side that just
+    /// moved is not in check.
+    fn is_legal(&self) -> bool {
+        let pawns_ok = (0..self.len)
+            .filter(|&i| self.pieces[i].piece_type() == PieceType::Pawn)
+            .all(|i| !matches!(Square::new(self.squares[i]).rank(), Rank::R1 | Rank::R8));
+
+        self.occupancy().popcount() == self.len && pawns_ok && !self.attacked(self.king(!self.stm), self.stm)
+    }
+}
+
+/// Applies one of the eight symmetries of the board to a square.
+const fn transform(square: u8, symmetry: usize) -> u8 {
+    let (mut file, mut rank) = (square & 7, square >> 3);
+    if symmetry & 1 != 0 {
+        file = 7 - file;
+    }
+    if symmetry & 2 != 0 {
+        rank = 7 - rank;
+    }
+    if symmetry & 4 != 0 {
+        (file, rank) = (rank, file);
+    }
+    (rank << 3) | file
+}
+
+/// Maps the positions of a material to indices. Positions that are mirror images of each other
+/// share the smallest of their indices, which has the white king in a fixed region: files a-d
+/// when there are pawns, otherwise the a1-d1-d4 triangle.
+///
+/// The kings index a list of the pairs of king squares that can stand next to each other in
+/// that region. Each group of identical pieces, pawns first, then indexes the combination of
+/// squares it takes among those the earlier pieces left free, so that no index is spent on
+/// overlapping pieces or on permutations of identical ones.
+struct Layout {
+    material: Material,
+    /// Only the mirror across the d and e files keeps pawns moving the same way.
+    symmetries: usize,
+    /// Index of each pair of king squares in `kings`, or `u16::MAX` if it is not canonical.
+    pairs: Vec<u16>,
+    kings: Vec<(u8, u8)>,
+    /// The first index of each pair of king squares, followed by the number of indices of a
+    /// side to move.
+    offsets: Vec<usize>,
+    /// The ranges of identical pieces in the material, in the order they are indexed.
+    groups: Vec<Range<usize>>,
+    size: usize,
+}
+
+impl Layout {
+    fn new(material: Material) -> Self {
+        let pawns = material.has_pawns();
+        let symmetries = if pawns { 2 } else { 8 };
+        let in_region = |square: u8| square & 7 < 4 && (pawns || square >> 3 <= square & 7);
+
+        let mut groups = Vec::new();
+        let mut start = 2;
+        while start < material.0.len() {
+            let end =
+                (start..material.0.len()).find(|&i| material.0[i] != material.0[start]).unwrap_or(material.0.len());
+            groups.push(start..end);
+            start = end;
+        }
+        groups.sort_by_key(|group| material.0[group.start].piece_type() != PieceType::Pawn);
+
+        let mut layout = Self {
+            material,
+            symmetries,
+            pairs: vec![u16::MAX; Square::NUM * Square::NUM],
+            kings: Vec::new(),
+            offsets: vec![0],
+            groups,
+            size: 0,
+        };
+
+        for white in 0..Square::NUM as u8 {
+            for black in 0..Square::NUM as u8 {
+                let distance = ((white & 7).abs_diff(black & 7)).max((white >> 3).abs_diff(black >> 3));
+                let canonical = (0..symmetries).all(|symmetry| {
+                    let mirrored = (transform(white, symmetry), transform(black, symmetry));
+                    !in_region(mirrored.0) || mirrored >= (white, black)
+                });
+
+                if distance < 2 || !in_region(white) || !canonical {
+                    continue;
+                }
+
+                let kings = 1 << white | 1 << black;
+                let count = (0..layout.groups.len()).map(|group| layout.combinations(kings, group)).product::<usize>();
+
+                layout.pairs[white as usize * Square::NUM + black as usize] = layout.kings.len() as u16;
+                layout.kings.push((white, black));
+                layout.offsets.push(layout.offsets.last().unwrap() + count);
+            }
+        }
+
+        layout.size = Color::NUM * layout.offsets.last().unwrap();
+        layout
+    }
+
+    /// The squares a group may take, before the squares of the earlier groups are removed.
+    fn area(&self, group: usize) -> u64 {
+        if self.material.0[self.groups[group].start].piece_type() == PieceType::Pawn { PAWN_SQUARES } else { !0 }
+    }
+
+    /// The number of combinations of squares of a group, given the squares of the kings.
+    fn combinations(&self, kings: u64, group: usize) -> usize {
+        let earlier = self.groups[..group].iter().map(ExactSizeIterator::len).sum::<usize>();
+        let available = (self.area(group) & !kings).count_ones() as usize - earlier;
+        binomial(available, self.groups[group].len())
+    }
+
+    /// The index of a normalized position of this material.
+    fn index(&self, position: &Position) -> usize {
+        let len = position.len;
+        let mut best = usize::MAX;
+
+        for symmetry in 0..self.symmetries {
+            let mut squares = [0; MAX_PIECES];
+            for (square, &original) in squares.iter_mut().zip(&position.squares[..len]) {
+                *square = transform(original, symmetry);
+            }
+
+            let pair = self.pairs[squares[0] as usize * Square::NUM + squares[1] as usize];
+            if pair == u16::MAX {
+                continue;
+            }
+
+            let kings = 1 << squares[0] | 1 << squares[1];
+            let mut occupancy: u64 = kings;
+            let mut index = 0;
+
+            for (group, range) in self.groups.iter().enumerate() {
+                let free = self.area(group) & !occupancy;
+
+                let mut ranks = [0; MAX_PIECES];
+                for (rank, &square) in ranks.iter_mut().zip(&squares[range.clone()]) {
+                    *rank = (free & ((1 << square) - 1)).count_ones() as usize;
+                    occupancy |= 1 << square;
+                }
+
+                let ranks = &mut ranks[..range.len()];
+                ranks.sort_unstable();
+
+                let combination = ranks.iter().enumerate().map(|(i, &rank)| binomial(rank, i + 1)).sum::<usize>();
+                index = index * self.combinations(kings, group) + combination;
+            }
+
+            let base = position.stm as usize * self.offsets[self.kings.len()] + self.offsets[pair as usize];
+            best = best.min(base + index);
+        }
+
+        best
+    }
+
+    fn decode(&self, index: usize) -> Position {
+        let len = self.material.0.len();
+        let mut position = Position {
+            pieces: [Piece::None; MAX_PIECES],
+            squares: [0; MAX_PIECES],
+            len,
+            stm: Color::new((index / self.offsets[self.kings.len()]) as u8),
+        };
+        position.pieces[..len].copy_from_slice(&self.material.0);
+
+        let mut index = index % self.offsets[self.kings.len()];
+        let pair = self.offsets.partition_point(|&offset| offset <= index) - 1;
+        index -= self.offsets[pair];
+
+        (position.squares[0], position.squares[1]) = self.kings[pair];
+        let kings = 1 << position.squares[0] | 1 << position.squares[1];
+
+        let mut combinations = [0; MAX_PIECES];
+        for group in (0..self.groups.len()).rev() {
+            let count = self.combinations(kings, group);
+            combinations[group] = index % count;
+            index /= count;
+        }
+
+        let mut occupancy: u64 = kings;
+        for (group, range) in self.groups.iter().enumerate() {
+            let free = self.area(group) & !occupancy;
+
+            let mut combination = combinations[group];
+            for i in (0..range.len()).rev() {
+                let mut rank = i;
+                while binomial(rank + 1, i + 1) <= combination {
+                    rank += 1;
+                }
+                combination -= binomial(rank, i + 1);
+
+                let mut candidates = free;
+                for _ in 0..rank {
+                    candidates &= candidates - 1;
+                }
+
+                let square = candidates.trailing_zeros() as u8;
+                position.squares[range.start + i] = square;
+                occupancy |= 1 << square;
+            }
+        }
+
+        position
+    }
+}
+
+const fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+
+    let mut result = 1;
+    let mut i = 0;
+    while i < k {
+        result = result * (n - i) / (i + 1);
+        i += 1;
+    }
+    result
+}
+
+struct Table {
+    layout: Layout,
+    values: Vec<u8>,
+}
+
+impl Table {
+    fn probe(&self, position: &Position) -> u8 {
+        self.values[self.layout.index(position)]
+    }
+
+    fn read(material: Material, path: &Path) -> io::Result<Self> {
+        let layout = Layout::new(material);
+        let mut file = fs::File::open(path)?;
+
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || u64::from_le_bytes(header[4..].try_into().unwrap()) != layout.size as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{}' is not a valid table", path.display()),
+            ));
+        }
+
+        let mut values = vec![0; layout.size];
+        file.read_exact(&mut values)?;
+        Ok(Self { layout, values })
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&(self.values.len() as u64).to_le_bytes())?;
+        file.write_all(&self.values)?;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lookup;
+
+    fn tables(signature: &str) -> Vec<Table> {
+        lookup::initialize();
+
+        let mut tables = Vec::new();
+        build(&Material::parse(signature).unwrap(), &mut tables, None).unwrap();
+        tables
+    }
+
+    fn outcome(tables: &[Table], fen: &str) -> Outcome {
+        let board = Board::from_fen(fen).unwrap();
+        Outcome::from_value(lookup(tables, &Position::from_board(&board)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_signatures() {
+        assert_eq!(Material::parse("kqkr").unwrap().to_string(), "KQKR");
+        assert_eq!(Material::parse("KRKBP").unwrap().flipped().to_string(), "KBPKR");
+        assert!(Material::parse("KQQQKR").is_none());
+        assert!(Material::parse("QKK").is_none());
+        assert!(Material::parse("KQR").is_none());
+    }
+
+    #[test]
+    fn rook_endgame_distances() {
+        let tables = tables("KRK");
+
+        assert_eq!(outcome(&tables, "k7/8/1K6/8/8/8/8/7R w - - 0 1"), Outcome::Win(1));
+        assert_eq!(outcome(&tables, "R1k5/8/2K5/8/8/8/8/8 b - - 0 1"), Outcome::Loss(0));
+        // Either color may hold the rook
+        assert_eq!(outcome(&tables, "7r/8/8/8/8/1k6/8/K7 b - - 0 1"), Outcome::Win(1));
+        // The rook hangs
+        assert_eq!(outcome(&tables, "8/8/8/8/8/8/2k5/K1R5 b - - 0 1"), Outcome::Draw);
+        // The longest win is a mate in 16
+        let longest = tables[0].values.iter().filter(|&&value| value % 2 == 0).max();
+        assert_eq!(longest.copied().and_then(Outcome::from_value), Some(Outcome::Win(31)));
+    }
+
+    #[test]
+    fn bishop_and_knight_distances() {
+        let tables = tables("KBNK");
+
+        assert_eq!(outcome(&tables, "7k/4N3/6K1/8/8/8/7B/8 w - - 0 1"), Outcome::Win(1));
+        // Capturing the knight draws
+        assert_eq!(outcome(&tables, "8/8/8/8/8/K7/2N5/1kB5 b - - 0 1"), Outcome::Draw);
+        // The longest win is a mate in 33
+        let longest = tables[0].values.iter().filter(|&&value| value % 2 == 0).max();
+        assert_eq!(longest.copied().and_then(Outcome::from_value), Some(Outcome::Win(65)));
+    }
+
+    #[test]
+    fn long_distances_are_not_probed() {
+        assert_eq!(Outcome::from_value(LONG_WIN), None);
+        assert_eq!(Outcome::from_value(LONG_LOSS), None);
+        assert_eq!(Outcome::at_least(LONG_WIN), Outcome::Win(MAX_DTM + 1));
+        assert_eq!(Outcome::at_least(LONG_LOSS), Outcome::Loss(MAX_DTM + 2));
+        assert_eq!(Outcome::from_value(MAX_DTM as u8 + 1), Some(Outcome::Loss(MAX_DTM)));
+    }
+
+    #[test]
+    fn layouts_skip_impossible_indices() {
+        // The 462 and 1806 canonical pairs of king squares without and with pawns
+        assert_eq!(Layout::new(Material::parse("KRK").unwrap()).size, 2 * 462 * 62);
+        assert_eq!(Layout::new(Material::parse("KNNK").unwrap()).size, 2 * 462 * 62 * 61 / 2);
+        assert_eq!(Layout::new(Material::parse("KPKP").unwrap()).kings.len(), 1806);
+    }
+
+    #[test]
+    fn pawn_endgame_outcomes() {
+        let tables = tables("KPK");
+
+        assert!(matches!(outcome(&tables, "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Outcome::Win(_)));
+        assert!(matches!(outcome(&tables, "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Outcome::Loss(_)));
+        assert_eq!(outcome(&tables, "k7/8/8/8/8/8/P7/K7 w - - 0 1"), Outcome::Draw);
+        // Stalemate
+        assert_eq!(outcome(&tables, "4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Outcome::Draw);
+        assert!(matches!(outcome(&tables, "8/8/8/8/8/1k6/p7/2K5 w - - 0 1"), Outcome::Loss(_)));
+    }
+}
@@ -1,24 +1,19 @@
-use std::sync::atomic::Ordering;
+use std::{sync::atomic::Ordering, time::Duration};
 
 use crate::{
     evaluation::correct_eval,
     movepick::{MovePicker, Stage},
+    retrograde,
     stack::Stack,
+    tb,
     thread::{PlyArray, RootMove, Status, ThreadData},
-    time::Limits,
     transposition::{Bound, TtDepth},
     types::{
         ArrayVec, Color, MAX_PLY, Move, Piece, PieceType, Score, Square, draw, is_decisive, is_loss, is_valid, is_win,
-        mate_in, mated_in,
+        mate_in, mated_in, tb_loss_in, tb_win_in,
     },
 };
 
-#[cfg(feature = "syzygy")]
-use crate::{
-    tb,
-    types::{tb_loss_in, tb_win_in},
-};
-
 #[allow(unused_imports)]
 use crate::misc::{dbg_hit, dbg_stats};
 
@@ -59,6 +54,11 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
     td.nnue.full_refresh(&td.board);
 
     td.multi_pv = td.multi_pv.min(td.root_moves.len());
+    td.report = report;
+    td.last_info = Duration::ZERO;
+    td.contempt = td.shared.contempt.each_ref().map(|contempt| contempt.load(Ordering::Relaxed));
+    td.halted = false;
+    td.tablebases = td.shared.tablebases.prober();
 
     let mut average = vec![td.previous_best_score; td.multi_pv];
     let mut last_best_rootmove = RootMove::default();
@@ -66,6 +66,7 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
     let mut eval_stability = 0;
     let mut pv_stability = 0;
     let mut soft_stop_voted = false;
+    let mut pending_best_stats = Vec::new();
 
     if td.root_moves.is_empty() {
         if report == Report::Full {
@@ -76,11 +77,13 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
 
     // Iterative Deepening
     for depth in 1..MAX_PLY as i32 {
-        if td.id == 0
-            && let Limits::Depth(maximum) = td.time_manager.limits()
+        if (td.id == 0 || td.deterministic)
+            && let Some(maximum) = td.time_manager.limits().depth
             && depth > maximum
         {
-            td.shared.status.set(Status::STOPPED);
+            if !td.time_manager.is_pondering(td) {
+                td.shared.status.set(Status::STOPPED);
+            }
             break;
         }
 
@@ -134,7 +137,7 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
 
                 td.root_moves[td.pv_index..td.pv_end].sort_by_key(|rm| std::cmp::Reverse(rm.score));
 
-                if td.shared.status.get() == Status::STOPPED {
+                if td.stopped() {
                     break;
                 }
 
@@ -161,10 +164,12 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
                             (average[td.pv_index] + score) / 2
                         };
 
-                        td.shared.best_stats[td.pv_index].fetch_max(
-                            ((depth as u32) << 16) | (average[td.pv_index] + 32768) as u32,
-                            Ordering::AcqRel,
-                        );
+                        let stats = ((depth as u32) << 16) | (average[td.pv_index] + 32768) as u32;
+                        if td.deterministic {
+                            pending_best_stats.push((td.pv_index, stats));
+                        } else {
+                            td.shared.best_stats[td.pv_index].fetch_max(stats, Ordering::AcqRel);
+                        }
 
                         break;
                     }
@@ -178,7 +183,7 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
             }
         }
 
-        if td.shared.status.get() != Status::STOPPED {
+        if !td.stopped() {
             td.completed_depth = depth;
         }
 
@@ -202,7 +207,7 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
                 || td.root_moves[0].upperbound
                 || td.root_moves[0].lowerbound);
 
-        let is_aborted_loss = td.shared.status.get() == Status::STOPPED
+        let is_aborted_loss = td.stopped()
             && td.root_moves[0].score != -Score::INFINITE
             && is_loss(td.root_moves[0].score)
             && !td.root_moves[0].upperbound
@@ -217,28 +222,32 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
             } else if is_aborted_loss {
                 td.root_moves[0].lowerbound = true;
             }
-        } else if td.shared.status.get() != Status::STOPPED {
+        } else if !td.stopped() {
             last_best_rootmove = td.root_moves[0].clone();
         }
 
         if report == Report::Full
-            && !(is_loss(td.root_moves[0].display_score) && td.shared.status.get() == Status::STOPPED)
-            && (td.shared.status.get() == Status::STOPPED
-                || td.pv_index + 1 == td.multi_pv
-                || td.shared.nodes.aggregate() > 10_000_000)
+            && !(is_loss(td.root_moves[0].display_score) && td.stopped())
+            && (td.stopped() || td.pv_index + 1 == td.multi_pv || td.shared.nodes.aggregate() > 10_000_000)
         {
             td.print_uci_info(depth);
         }
 
-        if td.shared.status.get() == Status::STOPPED {
+        if td.deterministic {
+            let stop = td.halted || (td.id == 0 && !td.stopped() && mate_found(td));
+            if !merge_iteration(td, &mut pending_best_stats, stop, depth, thread_count) {
+                break;
+            }
+        }
+
+        if td.stopped() {
             break;
         }
 
-        if td.id == 0
-            && let Limits::Mate(moves) = td.time_manager.limits()
-            && Score::MATE - td.root_moves[0].score.abs() <= moves as i32 * 2
-        {
-            td.shared.status.set(Status::STOPPED);
+        if td.id == 0 && mate_found(td) {
+            if !td.time_manager.is_pondering(td) {
+                td.shared.status.set(Status::STOPPED);
+            }
             break;
         }
 
@@ -279,15 +288,16 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
             }
         }
 
-        if td.shared.status.get() == Status::STOPPED {
+        if td.stopped() {
             break;
         }
     }
 
-    if matches!(td.time_manager.limits(), Limits::Infinite) {
-        while td.shared.status.get() != Status::STOPPED {
-            std::hint::spin_loop();
-        }
+    // The bestmove must not be sent before `stop` in infinite mode, or before `ponderhit` while pondering
+    while td.shared.status.get() != Status::STOPPED
+        && (td.time_manager.limits().infinite || td.time_manager.is_pondering(td))
+    {
+        std::hint::spin_loop();
     }
 
     if report == Report::Minimal {
@@ -297,6 +307,70 @@ pub fn start(td: &mut ThreadData, report: Report, thread_count: usize) {
     td.previous_best_score = td.root_moves[0].score;
 }
 
+/// Returns `true` once the mate asked for by `go mate` has been found.
+fn mate_found(td: &ThreadData) -> bool {
+    td.time_manager.limits().mate.is_some_and(|moves| Score::MATE - td.root_moves[0].score.abs() <= moves as i32 * 2)
+}
+
+/// In deterministic mode the threads search each iteration in parallel, against what was merged
+/// before it. Once all are done, they merge their transposition table writes and root statistics
+/// in order of their ids, and wait for each other again. Returns `false` if any thread asked to
+/// `stop` after this iteration, or if the search was stopped from outside.
+fn merge_iteration(
+    td: &mut ThreadData, pending_best_stats: &mut Vec<(usize, u32)>, stop: bool, depth: i32, thread_count: usize,
+) -> bool {
+    if !synchronize(td, 2 * depth as usize - 1, thread_count) {
+        return false;
+    }
+
+    while td.shared.turn.load(Ordering::Acquire) != td.id {
+        if td.shared.status.get() == Status::STOPPED {
+            return false;
+        }
+        std::thread::yield_now();
+    }
+
+    if let Some(pending) = &mut td.pending_writes {
+        pending.merge_into(&td.shared.tt);
+    }
+
+    for (index, stats) in pending_best_stats.drain(..) {
+        td.shared.best_stats[index].fetch_max(stats, Ordering::AcqRel);
+    }
+
+    if stop {
+        td.shared.stop_after_merge.store(true, Ordering::Release);
+    }
+
+    td.shared.turn.store((td.id + 1) % thread_count, Ordering::Release);
+
+    synchronize(td, 2 * depth as usize, thread_count) && !td.shared.stop_after_merge.load(Ordering::Acquire)
+}
+
+/// Waits until all threads have reached the `point`th synchronization point of a deterministic
+/// search. Returns `false` if the search was stopped in the meantime.
+fn synchronize(td: &ThreadData, point: usize, thread_count: usize) -> bool {
+    td.shared.arrivals.fetch_add(1, Ordering::AcqRel);
+
+    while td.shared.arrivals.load(Ordering::Acquire) < point * thread_count {
+        if td.shared.status.get() == Status::STOPPED {
+            return false;
+        }
+        std::thread::yield_now();
+    }
+    true
+}
+
+/// Stops the search. In deterministic mode only this thread stops at once, and the others
+/// follow after merging the current iteration.
+fn stop(td: &mut ThreadData) {
+    if td.deterministic {
+        td.halted = true;
+    } else {
+        td.shared.status.set(Status::STOPPED);
+    }
+}
+
 fn search<NODE: NodeType>(
     td: &mut ThreadData, mut alpha: i32, mut beta: i32, depth: i32, cut_node: bool, ply: isize,
 ) -> i32 {
@@ -312,7 +386,7 @@ fn search<NODE: NodeType>(
         td.pv_table.clear(ply as usize);
     }
 
-    if td.shared.status.get() == Status::STOPPED {
+    if td.stopped() {
         return Score::ZERO;
     }
 
@@ -333,11 +407,15 @@ fn search<NODE: NodeType>(
         td.sel_depth = td.sel_depth.max(ply as i32);
     }
 
-    if td.id == 0 && td.time_manager.check_time(td) {
-        td.shared.status.set(Status::STOPPED);
+    if (td.id == 0 || td.deterministic) && td.time_manager.check_time(td) {
+        stop(td);
         return Score::ZERO;
     }
 
+    if td.id == 0 && td.report == Report::Full && td.nodes() & 4095 == 0 {
+        td.print_heartbeat();
+    }
+
     if !NODE::ROOT {
         if td.board.is_draw(ply) {
             return draw(td);
@@ -356,7 +434,6 @@ fn search<NODE: NodeType>(
         }
     }
 
-    #[cfg(feature = "syzygy")]
     let mut max_score = Score::INFINITE;
 
     let mut best_score = -Score::INFINITE;
@@ -364,7 +441,7 @@ fn search<NODE: NodeType>(
     let mut depth = depth.min(MAX_PLY as i32 - 1);
 
     let hash = td.board.hash();
-    let entry = td.shared.tt.read(hash, td.board.fiftymove_clock(), ply);
+    let entry = td.tt_read(hash, td.board.fiftymove_clock(), ply);
 
     let mut tt_depth = 0;
     let mut tt_move = Move::NULL;
@@ -407,21 +484,24 @@ fn search<NODE: NodeType>(
     }
 
     // Tablebases Probe
-    #[cfg(feature = "syzygy")]
     if !NODE::ROOT
         && !excluded
         && !td.shared.stop_probing_tb.load(Ordering::Relaxed)
         && td.board.fiftymove_clock() == 0
         && td.board.castling().raw() == 0
-        && td.board.occupancies().popcount() <= tb::size()
-        && let Some(outcome) = tb::probe(&td.board)
+        && tb::within_limits(&td.shared, td.board.occupancies().popcount(), depth)
+        && let Some(outcome) = td
+            .tablebases
+            .probe_wdl(&td.board)
+            .and_then(|wdl| wdl.outcome(td.shared.syzygy_50_move_rule.load(Ordering::Relaxed)))
     {
         td.shared.tb_hits.increment(td.id);
 
         let (score, bound) = match outcome {
             tb::GameOutcome::Win => (tb_win_in(ply), Bound::Lower),
             tb::GameOutcome::Loss => (tb_loss_in(ply), Bound::Upper),
-            tb::GameOutcome::Draw => (Score::ZERO, Bound::Exact),
+            // Exact draws are stored, so they take the contempt without the jitter of `draw`
+            tb::GameOutcome::Draw => (td.contempt[td.board.side_to_move()], Bound::Exact),
         };
 
         if bound == Bound::Exact
@@ -429,7 +509,7 @@ fn search<NODE: NodeType>(
             || (bound == Bound::Upper && score <= alpha)
         {
             let depth = (depth + 6).min(MAX_PLY as i32 - 1);
-            td.shared.tt.write(hash, depth, Score::NONE, score, bound, Move::NULL, ply, tt_pv, false);
+            td.tt_write(hash, depth, Score::NONE, score, bound, Move::NULL, ply, tt_pv, false);
             return score;
         }
 
@@ -443,6 +523,33 @@ fn search<NODE: NodeType>(
         }
     }
 
+    // Generated Tablebases Probe, for endings the Syzygy tablebases do not cover
+    if !NODE::ROOT
+        && !excluded
+        && td.board.occupancies().popcount() > tb::cardinality(&td.shared)
+        && let Some(outcome) = retrograde::probe(&td.board)
+    {
+        let rule50 = td.shared.syzygy_50_move_rule.load(Ordering::Relaxed);
+        let too_far = |plies: usize| {
+            ply as usize + plies >= MAX_PLY || (rule50 && td.board.fiftymove_clock() as usize + plies > 100)
+        };
+
+        let score = match outcome {
+            retrograde::Outcome::Win(plies) if !too_far(plies) => Some(mate_in(ply + plies as isize)),
+            retrograde::Outcome::Loss(plies) if !too_far(plies) => Some(mated_in(ply + plies as isize)),
+            retrograde::Outcome::Draw => Some(td.contempt[td.board.side_to_move()]),
+            _ => None,
+        };
+
+        if let Some(score) = score {
+            td.shared.tb_hits.increment(td.id);
+
+            let depth = (depth + 6).min(MAX_PLY as i32 - 1);
+            td.tt_write(hash, depth, Score::NONE, score, Bound::Exact, Move::NULL, ply, tt_pv, false);
+            return score;
+        }
+    }
+
     let correction_value = eval_correction(td, ply);
 
     let raw_eval;
@@ -462,7 +569,7 @@ fn search<NODE: NodeType>(
         raw_eval = td.nnue.evaluate(&td.board);
         eval = correct_eval(td, raw_eval, correction_value);
 
-        td.shared.tt.write(hash, TtDepth::SOME, raw_eval, Score::NONE, Bound::None, Move::NULL, ply, tt_pv, false);
+        td.tt_write(hash, TtDepth::SOME, raw_eval, Score::NONE, Bound::None, Move::NULL, ply, tt_pv, false);
     }
 
     // Prefer the TT entry to tighten the evaluation when its bound aligns with
@@ -605,7 +712,7 @@ fn search<NODE: NodeType>(
 
         td.board.undo_null_move();
 
-        if td.shared.status.get() == Status::STOPPED {
+        if td.stopped() {
             return Score::ZERO;
         }
 
@@ -620,7 +727,7 @@ fn search<NODE: NodeType>(
             let verified_score = search::<NonPV>(td, beta - 1, beta, reduced_depth, false, ply);
             td.nmp_min_ply = 0;
 
-            if td.shared.status.get() == Status::STOPPED {
+            if td.stopped() {
                 return Score::ZERO;
             }
 
@@ -671,12 +778,12 @@ fn search<NODE: NodeType>(
 
             undo_move(td, mv);
 
-            if td.shared.status.get() == Status::STOPPED {
+            if td.stopped() {
                 return Score::ZERO;
             }
 
             if score >= probcut_beta {
-                td.shared.tt.write(hash, probcut_depth + 1, raw_eval, score, Bound::Lower, mv, ply, tt_pv, false);
+                td.tt_write(hash, probcut_depth + 1, raw_eval, score, Bound::Lower, mv, ply, tt_pv, false);
 
                 if is_decisive(score) {
                     return score;
@@ -704,7 +811,7 @@ fn search<NODE: NodeType>(
         td.excluded[ply] = Move::NULL;
         td.stack[ply].tt_pv = tt_pv;
 
-        if td.shared.status.get() == Status::STOPPED {
+        if td.stopped() {
             return Score::ZERO;
         }
 
@@ -763,6 +870,10 @@ fn search<NODE: NodeType>(
         move_count += 1;
         td.stack[ply].move_count = move_count;
 
+        if NODE::ROOT && td.id == 0 && td.report == Report::Full {
+            td.print_currmove(mv, move_count as usize + td.pv_index);
+        }
+
         let is_quiet = mv.is_quiet();
         let is_direct_check = td.board.is_direct_check(mv);
 
@@ -976,7 +1087,7 @@ fn search<NODE: NodeType>(
 
         undo_move(td, mv);
 
-        if td.shared.status.get() == Status::STOPPED {
+        if td.stopped() {
             return Score::ZERO;
         }
 
@@ -1039,7 +1150,7 @@ fn search<NODE: NodeType>(
                 alpha = score;
 
                 if !(NODE::ROOT && td.pv_index > 0) && mv != tt_move {
-                    td.shared.tt.write(hash, depth, raw_eval, score, Bound::Lower, mv, ply, true, false);
+                    td.tt_write(hash, depth, raw_eval, score, Bound::Lower, mv, ply, true, false);
                 }
             }
         }
@@ -1154,13 +1265,12 @@ fn search<NODE: NodeType>(
 
     tt_pv |= !NODE::ROOT && bound == Bound::Upper && move_count > 2 && td.stack[ply - 1].tt_pv;
 
-    #[cfg(feature = "syzygy")]
     if NODE::PV {
         best_score = best_score.min(max_score);
     }
 
     if !(excluded || NODE::ROOT && td.pv_index > 0) {
-        td.shared.tt.write(hash, depth, raw_eval, best_score, bound, best_move, ply, tt_pv, NODE::PV);
+        td.tt_write(hash, depth, raw_eval, best_score, bound, best_move, ply, tt_pv, NODE::PV);
     }
 
     if !(in_check
@@ -1199,11 +1309,15 @@ fn qsearch<NODE: NodeType>(td: &mut ThreadData, mut alpha: i32, beta: i32, ply:
         td.sel_depth = td.sel_depth.max(ply as i32);
     }
 
-    if td.id == 0 && td.time_manager.check_time(td) {
-        td.shared.status.set(Status::STOPPED);
+    if (td.id == 0 || td.deterministic) && td.time_manager.check_time(td) {
+        stop(td);
         return Score::ZERO;
     }
 
+    if td.id == 0 && td.report == Report::Full && td.nodes() & 4095 == 0 {
+        td.print_heartbeat();
+    }
+
     if td.board.is_draw(ply) {
         return draw(td);
     }
@@ -1213,7 +1327,7 @@ fn qsearch<NODE: NodeType>(td: &mut ThreadData, mut alpha: i32, beta: i32, ply:
     }
 
     let hash = td.board.hash();
-    let entry = td.shared.tt.read(hash, td.board.fiftymove_clock(), ply);
+    let entry = td.tt_read(hash, td.board.fiftymove_clock(), ply);
 
     let mut tt_score = Score::NONE;
     let mut tt_bound = Bound::None;
@@ -1275,7 +1389,7 @@ fn qsearch<NODE: NodeType>(td: &mut ThreadData, mut alpha: i32, beta: i32, ply:
         }
 
         if entry.is_none() {
-            td.shared.tt.write(hash, TtDepth::SOME, raw_eval, best_score, Bound::Lower, Move::NULL, ply, tt_pv, false);
+            td.tt_write(hash, TtDepth::SOME, raw_eval, best_score, Bound::Lower, Move::NULL, ply, tt_pv, false);
         }
 
         return best_score;
@@ -1319,7 +1433,7 @@ fn qsearch<NODE: NodeType>(td: &mut ThreadData, mut alpha: i32, beta: i32, ply:
         let score = -qsearch::<NODE>(td, -beta, -alpha, ply + 1);
         undo_move(td, mv);
 
-        if td.shared.status.get() == Status::STOPPED {
+        if td.stopped() {
             return Score::ZERO;
         }
 
@@ -1364,7 +1478,7 @@ fn qsearch<NODE: NodeType>(td: &mut ThreadData, mut alpha: i32, beta: i32, ply:
 
     let bound = if best_score >= beta { Bound::Lower } else { Bound::Upper };
 
-    td.shared.tt.write(hash, TtDepth::SOME, raw_eval, best_score, bound, best_move, ply, tt_pv, false);
+    td.tt_write(hash, TtDepth::SOME, raw_eval, best_score, bound, best_move, ply, tt_pv, false);
 
     debug_assert!(alpha < beta);
     debug_assert!(-Score::INFINITE < best_score && best_score < Score::INFINITE);
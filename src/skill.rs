@@ -0,0 +1,147 @@
+use crate::{misc::SplitMix64, thread::RootMove, types::Score};
+
+pub const MIN_ELO: i32 = 1320;
+pub const MAX_ELO: i32 = 3190;
+pub const MAX_LEVEL: i32 = 20;
+
+struct Anchor {
+    elo: i32,
+    nodes: u64,
+    multi_pv: usize,
+    noise: i32,
+}
+
+/// Anchor points mapping a target Elo to the search node cap, the number of candidate
+/// lines and the maximum random bonus (in internal score units) added to each candidate.
+/// Values between two anchors are interpolated.
+///
+/// The values are provisional: they were set by hand and have not been measured against
+/// rated opponents, so `UCI_Elo` is the strength aimed for rather than one the engine is
+/// known to play at. Calibrating them takes a gauntlet at each anchor against engines with
+/// published ratings, adjusting the node cap until the performance matches the anchor.
+#[rustfmt::skip]
+const ANCHORS: [Anchor; 7] = [
+    Anchor { elo: 1320, nodes:       200, multi_pv: 8, noise: 600 },
+    Anchor { elo: 1600, nodes:       600, multi_pv: 6, noise: 400 },
+    Anchor { elo: 1900, nodes:     2_000, multi_pv: 5, noise: 250 },
+    Anchor { elo: 2200, nodes:     8_000, multi_pv: 4, noise: 150 },
+    Anchor { elo: 2500, nodes:    40_000, multi_pv: 4, noise:  80 },
+    Anchor { elo: 2800, nodes:   200_000, multi_pv: 3, noise:  40 },
+    Anchor { elo: 3190, nodes: 1_000_000, multi_pv: 2, noise:  15 },
+];
+
+#[derive(Copy, Clone)]
+pub struct Skill {
+    pub limit_strength: bool,
+    pub elo: i32,
+    pub level: i32,
+    pub seed: u64,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Self {
+            limit_strength: false,
+            elo: MAX_ELO,
+            level: MAX_LEVEL,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Handicap {
+    pub nodes: u64,
+    pub multi_pv: usize,
+    pub noise: i32,
+    pub seed: u64,
+}
+
+impl Skill {
+    /// Returns the target strength, or `None` when playing at full strength.
+    /// `UCI_LimitStrength` takes precedence over `Skill Level`.
+    pub fn target_elo(&self) -> Option<i32> {
+        if self.limit_strength {
+            Some(self.elo.clamp(MIN_ELO, MAX_ELO))
+        } else if self.level < MAX_LEVEL {
+            Some(MIN_ELO + (MAX_ELO - MIN_ELO) * self.level.max(0) / MAX_LEVEL)
+        } else {
+            None
+        }
+    }
+
+    pub fn handicap(&self) -> Option<Handicap> {
+        let elo = self.target_elo()?;
+
+        let upper = ANCHORS.iter().position(|c| c.elo >= elo).unwrap_or(ANCHORS.len() - 1).max(1);
+        let (lo, hi) = (&ANCHORS[upper - 1], &ANCHORS[upper]);
+        let t = ((elo - lo.elo) as f64 / (hi.elo - lo.elo) as f64).clamp(0.0, 1.0);
+
+        Some(Handicap {
+            nodes: (lo.nodes as f64 * (hi.nodes as f64 / lo.nodes as f64).powf(t)).round() as u64,
+            multi_pv: if t < 0.5 { lo.multi_pv } else { hi.multi_pv },
+            noise: lo.noise + ((hi.noise - lo.noise) as f64 * t).round() as i32,
+            seed: self.seed,
+        })
+    }
+}
+
+impl Handicap {
+    /// Returns the index of the root move to play. Every candidate receives a random bonus
+    /// of up to `noise` and the highest total wins. The choice depends only on the scores,
+    /// the position hash and the seed.
+    pub fn pick(&self, root_moves: &[RootMove], hash: u64) -> usize {
+        let mut rng = SplitMix64::new(self.seed ^ hash);
+
+        let mut best = 0;
+        let mut best_value = i64::MIN;
+
+        for (index, root_move) in root_moves.iter().enumerate() {
+            let score = if root_move.score != -Score::INFINITE { root_move.score } else { root_move.previous_score };
+
+            let bonus = rng.next() % (self.noise.max(0) as u64 + 1);
+            let value = score as i64 + bonus as i64;
+
+            if value > best_value {
+                best = index;
+                best_value = value;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn handicap_follows_the_target_elo() {
+        assert_eq!(Skill::default().handicap(), None);
+
+        let weakest = Skill { limit_strength: true, elo: 0, ..Default::default() }.handicap().unwrap();
+        assert_eq!((weakest.nodes, weakest.multi_pv, weakest.noise), (200, 8, 600));
+
+        let strongest = Skill { limit_strength: true, ..Default::default() }.handicap().unwrap();
+        assert_eq!((strongest.nodes, strongest.multi_pv, strongest.noise), (1_000_000, 2, 15));
+
+        let level = Skill { level: 10, ..Default::default() }.handicap().unwrap();
+        assert!(level.nodes > weakest.nodes && level.nodes < strongest.nodes);
+        assert!(level.noise < weakest.noise && level.noise > strongest.noise);
+    }
+
+    #[test]
+    fn pick_is_deterministic() {
+        let board = Board::starting_position();
+        let root_moves = (0..8).map(|i| RootMove { score: 50 - 10 * i, ..Default::default() }).collect::<Vec<_>>();
+
+        let handicap = Skill { level: 0, seed: 42, ..Default::default() }.handicap().unwrap();
+        let choice = handicap.pick(&root_moves, board.hash());
+        assert_eq!(choice, handicap.pick(&root_moves, board.hash()));
+
+        let exact = Handicap { noise: 0, ..handicap };
+        assert_eq!(exact.pick(&root_moves, board.hash()), 0);
+    }
+}
@@ -1,15 +1,23 @@
-use std::{ffi, mem, ptr, sync::atomic::Ordering};
+//! Endgame tablebases as seen by search.
+//!
+//! Search only talks to a [`TablebaseProber`], held by the [`SharedContext`]. The Syzygy
+//! tablebases loaded by Fathom are one implementation; builds without the `syzygy` feature
+//! use one that never finds a position.
+
+use std::sync::{
+    Arc, RwLock,
+    atomic::{AtomicUsize, Ordering},
+};
 
 use crate::{
-    bindings::{
-        TB_DRAW, TB_LARGEST, TB_LOSS, TB_MAX_MOVES, TB_WIN, TbRootMove, TbRootMoves, tb_init, tb_probe_root_dtz,
-        tb_probe_root_wdl, tb_probe_wdl,
-    },
     board::{Board, NullBoardObserver},
-    thread::{RootMove, ThreadData},
-    types::{Color, MAX_PLY, PieceType, Score},
+    thread::{SharedContext, ThreadData},
+    types::{MAX_PLY, Move, Score, mate_in, mated_in},
 };
 
+#[cfg(feature = "syzygy")]
+pub mod fathom;
+
 #[derive(Eq, PartialEq)]
 pub enum GameOutcome {
     Win,
@@ -17,148 +25,281 @@ pub enum GameOutcome {
     Draw,
 }
 
-static mut SIZE: usize = 0;
+/// The result of a WDL probe from the side to move's point of view. Cursed wins and blessed
+/// losses are decided only when the fifty-move rule is ignored.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
 
-pub fn initialize(path: &str) -> Option<usize> {
-    let cpath = ffi::CString::new(path).ok()?;
+impl Wdl {
+    /// The outcome of the game. Cursed wins and blessed losses only count as decisive when
+    /// the fifty-move rule is ignored.
+    pub const fn outcome(self, rule50: bool) -> Option<GameOutcome> {
+        match self {
+            Self::Win => Some(GameOutcome::Win),
+            Self::Loss => Some(GameOutcome::Loss),
+            Self::Draw => Some(GameOutcome::Draw),
+            Self::CursedWin if !rule50 => Some(GameOutcome::Win),
+            Self::BlessedLoss if !rule50 => Some(GameOutcome::Loss),
+            _ => None,
+        }
+    }
 
-    unsafe {
-        tb_init(cpath.as_ptr());
-        SIZE = TB_LARGEST as usize;
-    };
+    /// The same result from the point of view of the other side.
+    pub const fn flip(self) -> Self {
+        match self {
+            Self::Loss => Self::Win,
+            Self::BlessedLoss => Self::CursedWin,
+            Self::Draw => Self::Draw,
+            Self::CursedWin => Self::BlessedLoss,
+            Self::Win => Self::Loss,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RootProbe {
+    Dtz,
+    // Without Fathom, only the tests rank moves by WDL
+    #[cfg_attr(not(feature = "syzygy"), allow(dead_code))]
+    Wdl,
+}
+
+/// A legal move of a tablebase position with the rank and score assigned by the prober.
+pub struct RankedMove {
+    pub mv: Move,
+    pub rank: i32,
+    pub score: i32,
+}
 
-    match size() {
-        0 => None,
-        _ => Some(size()),
+/// A legal move with the outcome of playing it, from the point of view of the side to move.
+pub struct MoveDtz {
+    pub mv: Move,
+    pub wdl: Wdl,
+    pub dtz: u32,
+}
+
+pub trait TablebaseProber: Send + Sync {
+    /// The largest number of pieces covered, or zero if no tables are loaded.
+    fn max_pieces(&self) -> usize;
+
+    /// Probes the WDL tables, ignoring the fifty-move counter of the position.
+    fn probe_wdl(&self, board: &Board) -> Option<Wdl>;
+
+    /// Ranks every legal move of the position by DTZ, falling back to WDL if the DTZ tables
+    /// are missing.
+    fn rank_root(&self, board: &Board, rule50: bool) -> Option<(RootProbe, Vec<RankedMove>)>;
+
+    /// Probes the DTZ tables for the position and every legal move of it. Unlike search probes,
+    /// the outcomes take the fifty-move counter of the position into account.
+    ///
+    /// Returns `None` if a table is missing or the position has no legal moves.
+    fn probe_dtz(&self, _: &Board) -> Option<(Wdl, u32, Vec<MoveDtz>)> {
+        None
+    }
+}
+
+/// The prober of builds without tablebases.
+pub struct NoTablebases;
+
+impl TablebaseProber for NoTablebases {
+    fn max_pieces(&self) -> usize {
+        0
+    }
+
+    fn probe_wdl(&self, _: &Board) -> Option<Wdl> {
+        None
+    }
+
+    fn rank_root(&self, _: &Board, _: bool) -> Option<(RootProbe, Vec<RankedMove>)> {
+        None
     }
 }
 
-pub fn size() -> usize {
-    unsafe { SIZE }
+/// The prober used by search. Its piece count is kept in an atomic as well, because search
+/// checks it on every node and should not take the lock to do so. For the same reason, each
+/// search probes a snapshot of the prober taken when it starts, see [`Tablebases::prober`].
+pub struct Tablebases {
+    prober: RwLock<Arc<dyn TablebaseProber>>,
+    size: AtomicUsize,
 }
 
-pub fn probe(board: &Board) -> Option<GameOutcome> {
-    let ep_square = tb_en_passant_square(board);
+impl Default for Tablebases {
+    fn default() -> Self {
+        #[cfg(feature = "syzygy")]
+        let prober: Arc<dyn TablebaseProber> = Arc::new(fathom::Fathom);
+        #[cfg(not(feature = "syzygy"))]
+        let prober: Arc<dyn TablebaseProber> = Arc::new(NoTablebases);
 
-    let code = unsafe {
-        tb_probe_wdl(
-            board.colors(Color::White).0,
-            board.colors(Color::Black).0,
-            board.pieces(PieceType::King).0,
-            board.pieces(PieceType::Queen).0,
-            board.pieces(PieceType::Rook).0,
-            board.pieces(PieceType::Bishop).0,
-            board.pieces(PieceType::Knight).0,
-            board.pieces(PieceType::Pawn).0,
-            0,
-            0,
-            ep_square,
-            board.side_to_move() == Color::White,
-        )
-    };
+        Self {
+            size: AtomicUsize::new(prober.max_pieces()),
+            prober: RwLock::new(prober),
+        }
+    }
+}
+
+impl Tablebases {
+    // Without Fathom, only the tests replace the prober
+    #[cfg_attr(not(feature = "syzygy"), allow(dead_code))]
+    pub fn set(&self, prober: Box<dyn TablebaseProber>) {
+        let size = prober.max_pieces();
+        *self.prober.write().unwrap() = Arc::from(prober);
+        self.size.store(size, Ordering::Relaxed);
+    }
+
+    /// The current prober. Searches keep it until they finish, so a prober set in the
+    /// meantime is used from the next search on.
+    pub fn prober(&self) -> Arc<dyn TablebaseProber> {
+        self.prober.read().unwrap().clone()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Probes the WDL tables, ignoring the fifty-move counter of the position.
+    pub fn wdl(&self, board: &Board) -> Option<Wdl> {
+        self.prober.read().unwrap().probe_wdl(board)
+    }
 
-    match code {
-        TB_WIN => Some(GameOutcome::Win),
-        TB_LOSS => Some(GameOutcome::Loss),
-        TB_DRAW => Some(GameOutcome::Draw),
-        _ => None,
+    /// Probes the WDL tables. Cursed wins and blessed losses only count as decisive when
+    /// the fifty-move rule is ignored.
+    pub fn probe(&self, board: &Board, rule50: bool) -> Option<GameOutcome> {
+        self.wdl(board)?.outcome(rule50)
     }
+
+    /// Probes the DTZ tables for the position and every legal move of it.
+    pub fn dtz(&self, board: &Board) -> Option<(Wdl, u32, Vec<MoveDtz>)> {
+        self.prober.read().unwrap().probe_dtz(board)
+    }
+
+    /// Ranks the legal moves of the position, sorted from best to worst.
+    pub fn rank_root(&self, board: &Board, rule50: bool) -> Option<(RootProbe, Vec<RankedMove>)> {
+        let (probe, mut ranked) = self.prober.read().unwrap().rank_root(board, rule50)?;
+        ranked.sort_by_key(|ranked| std::cmp::Reverse(ranked.rank));
+        Some((probe, ranked))
+    }
+}
+
+/// The largest number of pieces probed, limited by `SyzygyProbeLimit`.
+pub fn cardinality(shared: &SharedContext) -> usize {
+    shared.tablebases.size().min(shared.syzygy_probe_limit.load(Ordering::Relaxed))
+}
+
+/// Returns `true` if a position with `pieces` pieces is probed at `depth` in search. Positions
+/// with the largest piece count are only probed from `SyzygyProbeDepth` on.
+pub fn within_limits(shared: &SharedContext, pieces: usize, depth: i32) -> bool {
+    let cardinality = cardinality(shared);
+    pieces < cardinality || (pieces == cardinality && depth >= shared.syzygy_probe_depth.load(Ordering::Relaxed))
 }
 
 pub fn rank_rootmoves(td: &mut ThreadData) {
-    let mut rootmoves_in_c: mem::MaybeUninit<TbRootMoves> = mem::MaybeUninit::uninit();
-    let ep_square = tb_en_passant_square(&td.board);
-
-    unsafe {
-        let tb_ptr = rootmoves_in_c.as_mut_ptr();
-
-        (*tb_ptr).size = td.root_moves.len().min(TB_MAX_MOVES as usize) as u32;
-
-        for (i, root_move) in td.root_moves.iter().enumerate() {
-            if i >= TB_MAX_MOVES as usize {
-                break;
-            }
-
-            let c_move_ptr = (*tb_ptr).moves.as_mut_ptr().add(i);
-            ptr::write(
-                c_move_ptr,
-                TbRootMove {
-                    move_: root_move.mv.to_tb_move(),
-                    pv: [0; MAX_PLY],
-                    pvSize: 0,
-                    tbScore: 0,
-                    tbRank: 0,
-                },
-            );
+    let rule50 = td.shared.syzygy_50_move_rule.load(Ordering::Relaxed);
+    let Some((probe, ranked)) = td.shared.tablebases.rank_root(&td.board, rule50) else {
+        return;
+    };
+
+    // The prober ranks every legal move, so moves excluded by `searchmoves` are simply not found.
+    for ranked in ranked {
+        if let Some(rm) = td.root_moves.iter_mut().find(|rm| rm.mv == ranked.mv) {
+            rm.tb_score = ranked.score;
+            rm.tb_rank = ranked.rank;
         }
+    }
+    td.root_moves.sort_by_key(|rm| std::cmp::Reverse(rm.tb_rank));
+
+    td.shared.root_in_tb.store(true, Ordering::Relaxed);
+
+    // Keep probing in search if DTZ is not available and we are winning
+    let stop_probing = probe == RootProbe::Dtz || td.root_moves[0].tb_score <= Score::ZERO;
+    td.shared.stop_probing_tb.store(stop_probing, Ordering::Relaxed);
+}
+
+/// The positions `extend_root_pvs` may probe in total, as it runs before the search starts.
+const PV_PROBE_BUDGET: usize = 256;
+
+/// Builds the tablebase line of the first `count` root moves. After the root move both sides
+/// play the best move ranked by DTZ until the game converts: a capture or pawn move resets the
+/// fifty-move counter. From there the line follows the moves stored by the search, as long as
+/// they keep the outcome given by the WDL tables. It ends when the game ends, a draw is
+/// claimable, the tables no longer apply or the probe budget is spent. A line that was ranked
+/// by DTZ throughout and ends in checkmate turns the root score into a mate score.
+pub fn extend_root_pvs(td: &mut ThreadData, count: usize) {
+    if !td.shared.root_in_tb.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let rule50 = td.shared.syzygy_50_move_rule.load(Ordering::Relaxed);
+    let mut budget = PV_PROBE_BUDGET;
+
+    for index in 0..count.min(td.root_moves.len()) {
+        let mut board = td.board.clone();
+        let mut line = Vec::new();
+        let mut dtz_only = true;
+        let mut converted = false;
+
+        board.make_move(td.root_moves[index].mv, &mut NullBoardObserver);
 
-        // Helper to copy back from C struct and sort
-        let update_rootmoves = |root_moves: &mut Vec<RootMove>, c_rootmoves: &TbRootMoves| {
-            for i in 0..c_rootmoves.size as usize {
-                let tb_move = c_rootmoves.moves[i].move_;
-                if let Some(rm) = root_moves.iter_mut().find(|rm| rm.mv.to_tb_move() == tb_move) {
-                    rm.tb_score = c_rootmoves.moves[i].tbScore;
-                    rm.tb_rank = c_rootmoves.moves[i].tbRank;
+        while budget > 0
+            && line.len() < MAX_PLY - 1
+            && !is_line_draw(&board, line.len() as isize + 1, rule50)
+            && board.has_legal_moves()
+        {
+            budget -= 1;
+
+            let mv = if converted {
+                dtz_only = false;
+                match wdl_preserving_move(td, &board) {
+                    Some(mv) => mv,
+                    None => break,
                 }
-            }
-            root_moves.sort_by_key(|rm| std::cmp::Reverse(rm.tb_rank));
-        };
+            } else {
+                let Some((probe, ranked)) = td.shared.tablebases.rank_root(&board, rule50) else {
+                    break;
+                };
+                let Some(best) = ranked.first() else {
+                    break;
+                };
+
+                dtz_only &= probe == RootProbe::Dtz;
+                best.mv
+            };
 
-        let dtz_success = tb_probe_root_dtz(
-            td.board.colors(Color::White).0,
-            td.board.colors(Color::Black).0,
-            td.board.pieces(PieceType::King).0,
-            td.board.pieces(PieceType::Queen).0,
-            td.board.pieces(PieceType::Rook).0,
-            td.board.pieces(PieceType::Bishop).0,
-            td.board.pieces(PieceType::Knight).0,
-            td.board.pieces(PieceType::Pawn).0,
-            td.board.fiftymove_clock() as u32,
-            0,
-            ep_square,
-            td.board.side_to_move() == Color::White,
-            td.board.has_repeated(),
-            true,
-            tb_ptr,
-        );
-
-        if dtz_success != 0 {
-            let c_rootmoves: &TbRootMoves = &*tb_ptr;
-            update_rootmoves(&mut td.root_moves, c_rootmoves);
-            td.shared.stop_probing_tb.store(true, Ordering::Relaxed);
-            td.shared.root_in_tb.store(true, Ordering::Relaxed);
-            return;
+            board.make_move(mv, &mut NullBoardObserver);
+            line.push(mv);
+            converted |= board.fiftymove_clock() == 0;
         }
 
-        // fallback to wdl
-        let wdl_success = tb_probe_root_wdl(
-            td.board.colors(Color::White).0,
-            td.board.colors(Color::Black).0,
-            td.board.pieces(PieceType::King).0,
-            td.board.pieces(PieceType::Queen).0,
-            td.board.pieces(PieceType::Rook).0,
-            td.board.pieces(PieceType::Bishop).0,
-            td.board.pieces(PieceType::Knight).0,
-            td.board.pieces(PieceType::Pawn).0,
-            td.board.fiftymove_clock() as u32,
-            0,
-            ep_square,
-            td.board.side_to_move() == Color::White,
-            true,
-            tb_ptr,
-        );
-
-        if wdl_success != 0 {
-            let c_rootmoves: &TbRootMoves = &*tb_ptr;
-            update_rootmoves(&mut td.root_moves, c_rootmoves);
-            td.shared.root_in_tb.store(true, Ordering::Relaxed);
-
-            // Keep probing in search if DTZ is not available and we are winning
-            td.shared.stop_probing_tb.store(td.root_moves[0].tb_score <= Score::ZERO, Ordering::Relaxed);
+        let ply = line.len() as isize + 1;
+        if dtz_only && board.in_check() && !board.has_legal_moves() {
+            // The side that delivered the last move mated; an odd ply count means the root side did.
+            td.root_moves[index].tb_score = if ply % 2 == 1 { mate_in(ply) } else { mated_in(ply) };
         }
+
+        td.root_moves[index].tb_pv = line;
     }
 }
 
+/// Returns the move the search stored for the position, if it keeps the outcome the WDL tables
+/// give the position.
+fn wdl_preserving_move(td: &ThreadData, board: &Board) -> Option<Move> {
+    let wdl = td.shared.tablebases.wdl(board)?;
+    let mv = td.tt_read(board.hash(), board.fiftymove_clock(), 0)?.mv;
+
+    if !board.generate_all_moves().iter().any(|entry| entry.mv == mv) {
+        return None;
+    }
+
+    let mut child = board.clone();
+    child.make_move(mv, &mut NullBoardObserver);
+    (td.shared.tablebases.wdl(&child)?.flip() == wdl).then_some(mv)
+}
+
 pub fn normalize_draw_ranks(td: &mut ThreadData) {
     if !td.shared.root_in_tb.load(Ordering::Relaxed) {
         return;
@@ -178,6 +319,188 @@ pub fn normalize_draw_ranks(td: &mut ThreadData) {
     td.root_moves.sort_by_key(|rm| std::cmp::Reverse(rm.tb_rank));
 }
 
-const fn tb_en_passant_square(board: &Board) -> u32 {
-    board.en_passant() as u32 & 0x3F
+fn is_line_draw(board: &Board, ply: isize, rule50: bool) -> bool {
+    if rule50 { board.is_draw(ply) } else { board.draw_by_material() || board.draw_by_repetition(ply as i32) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::{lookup, thread::RootMove, threadpool::ThreadPool, transposition::Bound};
+
+    type RankedRoot = (RootProbe, Vec<(&'static str, i32, i32)>);
+
+    /// Tablebase results for a handful of positions, keyed by FEN, so that search behaviour
+    /// can be tested without tablebase files.
+    #[derive(Default)]
+    struct InMemoryTablebases {
+        pieces: usize,
+        wdl: HashMap<String, Wdl>,
+        /// The rank and score of each ranked root move, in UCI notation.
+        roots: HashMap<String, RankedRoot>,
+    }
+
+    impl InMemoryTablebases {
+        fn new(pieces: usize) -> Self {
+            Self { pieces, ..Default::default() }
+        }
+
+        fn with_wdl(mut self, fen: &str, wdl: Wdl) -> Self {
+            self.wdl.insert(Board::from_fen(fen).unwrap().to_fen(), wdl);
+            self
+        }
+
+        fn with_root(mut self, fen: &str, probe: RootProbe, moves: &[(&'static str, i32, i32)]) -> Self {
+            self.roots.insert(Board::from_fen(fen).unwrap().to_fen(), (probe, moves.to_vec()));
+            self
+        }
+    }
+
+    impl TablebaseProber for InMemoryTablebases {
+        fn max_pieces(&self) -> usize {
+            self.pieces
+        }
+
+        fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+            self.wdl.get(&board.to_fen()).copied()
+        }
+
+        fn rank_root(&self, board: &Board, _: bool) -> Option<(RootProbe, Vec<RankedMove>)> {
+            let (probe, moves) = self.roots.get(&board.to_fen())?;
+            let ranked = moves
+                .iter()
+                .map(|&(uci, rank, score)| RankedMove { mv: board.find_uci_move(uci).unwrap(), rank, score })
+                .collect();
+            Some((*probe, ranked))
+        }
+    }
+
+    /// Ranks the root moves of `fen` the way a search would before starting.
+    fn ranked_root(prober: InMemoryTablebases, fen: &str, rule50: bool) -> (ThreadPool, Arc<SharedContext>) {
+        lookup::initialize();
+
+        let shared = Arc::new(SharedContext::default());
+        shared.tablebases.set(Box::new(prober));
+        shared.syzygy_50_move_rule.store(rule50, Ordering::Relaxed);
+
+        let mut threads = ThreadPool::new(shared.clone());
+        let td = &mut threads[0];
+        td.board = Board::from_fen(fen).unwrap();
+        td.root_moves =
+            td.board.generate_all_moves().iter().map(|entry| RootMove { mv: entry.mv, ..Default::default() }).collect();
+
+        rank_rootmoves(td);
+        normalize_draw_ranks(td);
+        (threads, shared)
+    }
+
+    fn root_move<'a>(td: &'a ThreadData, uci: &str) -> &'a RootMove {
+        td.root_moves.iter().find(|rm| rm.mv.to_uci(&td.board) == uci).unwrap()
+    }
+
+    #[test]
+    fn cursed_wins_depend_on_the_fifty_move_rule() {
+        const FEN: &str = "8/8/8/8/8/2k5/8/KR6 w - - 0 1";
+        let prober = || InMemoryTablebases::new(3).with_wdl(FEN, Wdl::CursedWin);
+        let board = Board::from_fen(FEN).unwrap();
+
+        let tablebases = Tablebases::default();
+        tablebases.set(Box::new(prober()));
+        assert_eq!(tablebases.size(), 3);
+        assert!(tablebases.probe(&board, true).is_none());
+        assert!(matches!(tablebases.probe(&board, false), Some(GameOutcome::Win)));
+
+        let root = |rule50| {
+            let prober = prober().with_root(FEN, RootProbe::Dtz, &[("b1b8", 1000, 31000), ("b1b2", 500, 30500)]);
+            ranked_root(prober, FEN, rule50)
+        };
+
+        let (threads, shared) = root(true);
+        assert!(shared.root_in_tb.load(Ordering::Relaxed));
+        assert!(shared.stop_probing_tb.load(Ordering::Relaxed));
+        assert_eq!(threads[0].root_moves[0].mv.to_uci(&threads[0].board), "b1b8");
+
+        let cursed = root_move(&threads[0], "b1b2");
+        assert_eq!(cursed.tablebase_report(0, true), Some((0, &[][..])));
+        assert_eq!(root_move(&threads[0], "b1b8").tablebase_report(0, true), Some((31000, &[][..])));
+
+        let (threads, _) = root(false);
+        assert_eq!(root_move(&threads[0], "b1b2").tablebase_report(0, false), Some((30500, &[][..])));
+    }
+
+    #[test]
+    fn draws_by_the_fifty_move_rule_lose_their_rank() {
+        const FEN: &str = "8/8/8/8/8/2k5/7P/KR6 w - - 99 80";
+        let prober =
+            InMemoryTablebases::new(4).with_root(FEN, RootProbe::Dtz, &[("b1b8", 1000, 31000), ("h2h4", 990, 30990)]);
+
+        let (threads, _) = ranked_root(prober, FEN, true);
+        let td = &threads[0];
+
+        assert_eq!(td.root_moves[0].mv.to_uci(&td.board), "h2h4");
+        assert_eq!(td.root_moves[0].tb_rank, 990);
+        assert_eq!((root_move(td, "b1b8").tb_rank, root_move(td, "b1b8").tb_score), (0, Score::ZERO));
+    }
+
+    /// The tablebase line of the first root move after extending it.
+    fn extended_line(threads: &mut ThreadPool) -> String {
+        extend_root_pvs(&mut threads[0], 1);
+
+        let td = &threads[0];
+        let mut board = td.board.clone();
+        board.make_move(td.root_moves[0].mv, &mut NullBoardObserver);
+
+        let mut line = Vec::new();
+        for &mv in &td.root_moves[0].tb_pv {
+            line.push(mv.to_uci(&board));
+            board.make_move(mv, &mut NullBoardObserver);
+        }
+        line.join(" ")
+    }
+
+    #[test]
+    fn root_pvs_follow_dtz_until_the_game_converts() {
+        const ROOT: &str = "n7/8/8/8/8/2k5/8/KR6 w - - 0 1";
+        const CONVERTED: &str = "R7/8/8/8/3k4/8/8/K7 b - - 0 2";
+
+        let prober = InMemoryTablebases::new(4)
+            .with_root(ROOT, RootProbe::Dtz, &[("b1b8", 1000, 31000)])
+            .with_root("nR6/8/8/8/8/2k5/8/K7 b - - 1 1", RootProbe::Dtz, &[("c3d4", -1000, -31000)])
+            .with_root("nR6/8/8/8/3k4/8/8/K7 w - - 2 2", RootProbe::Dtz, &[("b8a8", 1000, 31000)])
+            .with_wdl(CONVERTED, Wdl::Loss)
+            .with_wdl("R7/8/8/8/8/2k5/8/K7 w - - 1 3", Wdl::Win)
+            .with_wdl("R7/8/8/8/4k3/8/8/K7 w - - 1 3", Wdl::Draw);
+
+        let (mut threads, shared) = ranked_root(prober, ROOT, true);
+        assert_eq!(extended_line(&mut threads), "c3d4 b8a8");
+
+        // Past the conversion only search moves that keep the tablebase outcome are followed
+        let converted = Board::from_fen(CONVERTED).unwrap();
+        let store = |uci| {
+            let mv = converted.find_uci_move(uci).unwrap();
+            shared.tt.write(converted.hash(), 1, Score::NONE, Score::NONE, Bound::Lower, mv, 0, false, false);
+        };
+
+        store("d4e4");
+        assert_eq!(extended_line(&mut threads), "c3d4 b8a8");
+
+        store("d4c3");
+        assert_eq!(extended_line(&mut threads), "c3d4 b8a8 d4c3");
+        assert_eq!(threads[0].root_moves[0].tb_score, 31000);
+    }
+
+    #[test]
+    fn empty_rankings_end_root_pvs() {
+        const ROOT: &str = "n7/8/8/8/8/2k5/8/KR6 w - - 0 1";
+
+        let prober = InMemoryTablebases::new(4)
+            .with_root(ROOT, RootProbe::Dtz, &[("b1b8", 1000, 31000)])
+            .with_root("nR6/8/8/8/8/2k5/8/K7 b - - 1 1", RootProbe::Dtz, &[("c3d4", -1000, -31000)])
+            .with_root("nR6/8/8/8/3k4/8/8/K7 w - - 2 2", RootProbe::Dtz, &[]);
+
+        let (mut threads, _) = ranked_root(prober, ROOT, true);
+        assert_eq!(extended_line(&mut threads), "c3d4");
+    }
 }
@@ -0,0 +1,224 @@
+use std::{ffi, mem, ptr};
+
+use super::{MoveDtz, RankedMove, RootProbe, TablebaseProber, Wdl};
+use crate::{
+    bindings::{
+        TB_BLESSED_LOSS, TB_CURSED_WIN, TB_DRAW, TB_LARGEST, TB_LOSS, TB_MAX_MOVES, TB_RESULT_DTZ_MASK,
+        TB_RESULT_DTZ_SHIFT, TB_RESULT_FAILED, TB_RESULT_FROM_MASK, TB_RESULT_FROM_SHIFT, TB_RESULT_PROMOTES_MASK,
+        TB_RESULT_PROMOTES_SHIFT, TB_RESULT_TO_MASK, TB_RESULT_TO_SHIFT, TB_RESULT_WDL_MASK, TB_RESULT_WDL_SHIFT,
+        TB_WIN, TbMove, TbRootMove, TbRootMoves, tb_init, tb_probe_root_dtz, tb_probe_root_impl, tb_probe_root_wdl,
+        tb_probe_wdl,
+    },
+    board::Board,
+    types::{Color, MAX_PLY, PieceType},
+};
+
+/// The Syzygy tablebases loaded by Fathom. Fathom keeps its tables in global state, so every
+/// instance probes whatever the last call to [`initialize`] loaded.
+pub struct Fathom;
+
+/// Loads the tablebases found in `path`, returning the largest piece count if any were found.
+pub fn initialize(path: &str) -> Option<usize> {
+    let cpath = ffi::CString::new(path).ok()?;
+
+    unsafe { tb_init(cpath.as_ptr()) };
+
+    match Fathom.max_pieces() {
+        0 => None,
+        size => Some(size),
+    }
+}
+
+impl TablebaseProber for Fathom {
+    fn max_pieces(&self) -> usize {
+        unsafe { TB_LARGEST as usize }
+    }
+
+    fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        let code = unsafe {
+            tb_probe_wdl(
+                board.colors(Color::White).0,
+                board.colors(Color::Black).0,
+                board.pieces(PieceType::King).0,
+                board.pieces(PieceType::Queen).0,
+                board.pieces(PieceType::Rook).0,
+                board.pieces(PieceType::Bishop).0,
+                board.pieces(PieceType::Knight).0,
+                board.pieces(PieceType::Pawn).0,
+                0,
+                0,
+                tb_en_passant_square(board),
+                board.side_to_move() == Color::White,
+            )
+        };
+
+        to_wdl(code)
+    }
+
+    fn rank_root(&self, board: &Board, rule50: bool) -> Option<(RootProbe, Vec<RankedMove>)> {
+        let moves = board.generate_all_moves().iter().map(|entry| entry.mv).collect::<Vec<_>>();
+
+        let mut rootmoves_in_c: mem::MaybeUninit<TbRootMoves> = mem::MaybeUninit::uninit();
+        let ep_square = tb_en_passant_square(board);
+
+        unsafe {
+            let tb_ptr = rootmoves_in_c.as_mut_ptr();
+
+            (*tb_ptr).size = moves.len().min(TB_MAX_MOVES as usize) as u32;
+
+            for (i, mv) in moves.iter().enumerate() {
+                if i >= TB_MAX_MOVES as usize {
+                    break;
+                }
+
+                let c_move_ptr = (*tb_ptr).moves.as_mut_ptr().add(i);
+                ptr::write(
+                    c_move_ptr,
+                    TbRootMove {
+                        move_: mv.to_tb_move(),
+                        pv: [0; MAX_PLY],
+                        pvSize: 0,
+                        tbScore: 0,
+                        tbRank: 0,
+                    },
+                );
+            }
+
+            let dtz_success = tb_probe_root_dtz(
+                board.colors(Color::White).0,
+                board.colors(Color::Black).0,
+                board.pieces(PieceType::King).0,
+                board.pieces(PieceType::Queen).0,
+                board.pieces(PieceType::Rook).0,
+                board.pieces(PieceType::Bishop).0,
+                board.pieces(PieceType::Knight).0,
+                board.pieces(PieceType::Pawn).0,
+                board.fiftymove_clock() as u32,
+                0,
+                ep_square,
+                board.side_to_move() == Color::White,
+                board.has_repeated(),
+                rule50,
+                tb_ptr,
+            );
+
+            let probe = if dtz_success != 0 {
+                RootProbe::Dtz
+            } else {
+                // fallback to wdl
+                let wdl_success = tb_probe_root_wdl(
+                    board.colors(Color::White).0,
+                    board.colors(Color::Black).0,
+                    board.pieces(PieceType::King).0,
+                    board.pieces(PieceType::Queen).0,
+                    board.pieces(PieceType::Rook).0,
+                    board.pieces(PieceType::Bishop).0,
+                    board.pieces(PieceType::Knight).0,
+                    board.pieces(PieceType::Pawn).0,
+                    board.fiftymove_clock() as u32,
+                    0,
+                    ep_square,
+                    board.side_to_move() == Color::White,
+                    rule50,
+                    tb_ptr,
+                );
+
+                if wdl_success == 0 {
+                    return None;
+                }
+                RootProbe::Wdl
+            };
+
+            let c_rootmoves: &TbRootMoves = &*tb_ptr;
+
+            let mut ranked = Vec::new();
+            for i in 0..c_rootmoves.size as usize {
+                let tb_move = c_rootmoves.moves[i].move_;
+                if let Some(&mv) = moves.iter().find(|mv| mv.to_tb_move() == tb_move) {
+                    ranked.push(RankedMove {
+                        mv,
+                        rank: c_rootmoves.moves[i].tbRank,
+                        score: c_rootmoves.moves[i].tbScore,
+                    });
+                }
+            }
+
+            Some((probe, ranked))
+        }
+    }
+
+    fn probe_dtz(&self, board: &Board) -> Option<(Wdl, u32, Vec<MoveDtz>)> {
+        probe_dtz(board)
+    }
+}
+
+fn probe_dtz(board: &Board) -> Option<(Wdl, u32, Vec<MoveDtz>)> {
+    let moves = board.generate_all_moves().iter().map(|entry| entry.mv).collect::<Vec<_>>();
+    if moves.is_empty() {
+        return None;
+    }
+
+    let mut results = vec![TB_RESULT_FAILED; TB_MAX_MOVES as usize + 1];
+
+    let result = unsafe {
+        tb_probe_root_impl(
+            board.colors(Color::White).0,
+            board.colors(Color::Black).0,
+            board.pieces(PieceType::King).0,
+            board.pieces(PieceType::Queen).0,
+            board.pieces(PieceType::Rook).0,
+            board.pieces(PieceType::Bishop).0,
+            board.pieces(PieceType::Knight).0,
+            board.pieces(PieceType::Pawn).0,
+            board.fiftymove_clock() as u32,
+            tb_en_passant_square(board),
+            board.side_to_move() == Color::White,
+            results.as_mut_ptr(),
+        )
+    };
+
+    if result == TB_RESULT_FAILED {
+        return None;
+    }
+
+    let mut probed = Vec::new();
+    for &result in results.iter().take_while(|&&result| result != TB_RESULT_FAILED) {
+        let from = (result & TB_RESULT_FROM_MASK) >> TB_RESULT_FROM_SHIFT;
+        let to = (result & TB_RESULT_TO_MASK) >> TB_RESULT_TO_SHIFT;
+        let promotes = (result & TB_RESULT_PROMOTES_MASK) >> TB_RESULT_PROMOTES_SHIFT;
+        let tb_move = ((promotes << 12) | (from << 6) | to) as TbMove;
+
+        if let Some(&mv) = moves.iter().find(|mv| mv.to_tb_move() == tb_move) {
+            probed.push(MoveDtz {
+                mv,
+                wdl: to_wdl(result_wdl(result))?,
+                dtz: result_dtz(result),
+            });
+        }
+    }
+
+    Some((to_wdl(result_wdl(result))?, result_dtz(result), probed))
+}
+
+const fn result_wdl(result: u32) -> u32 {
+    (result & TB_RESULT_WDL_MASK) >> TB_RESULT_WDL_SHIFT
+}
+
+const fn result_dtz(result: u32) -> u32 {
+    (result & TB_RESULT_DTZ_MASK) >> TB_RESULT_DTZ_SHIFT
+}
+
+const fn to_wdl(code: u32) -> Option<Wdl> {
+    match code {
+        TB_WIN => Some(Wdl::Win),
+        TB_CURSED_WIN => Some(Wdl::CursedWin),
+        TB_DRAW => Some(Wdl::Draw),
+        TB_BLESSED_LOSS => Some(Wdl::BlessedLoss),
+        TB_LOSS => Some(Wdl::Loss),
+        _ => None,
+    }
+}
+
+const fn tb_en_passant_square(board: &Board) -> u32 {
+    board.en_passant() as u32 & 0x3F
+}
@@ -2,8 +2,9 @@ use std::{
     ops::{Index, IndexMut},
     sync::{
         Arc,
-        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
     },
+    time::Duration,
 };
 
 #[cfg(target_arch = "wasm32")]
@@ -18,17 +19,19 @@ thread_local! {
 pub static WORKERS_REMAINING: AtomicUsize = AtomicUsize::new(0);
 
 use crate::{
-    board::Board,
+    board::{Board, NullBoardObserver},
     history::{
         ContinuationCorrectionHistory, ContinuationHistory, CorrectionHistory, NoisyHistory, PawnHistory, QuietHistory,
     },
     nnue::{Network, ParametersHandle},
     numa::{NumaConfig, NumaReplicable, NumaReplicated, NumaReplicatedAccessToken, NumaReplicationContext},
+    search::Report,
     stack::Stack,
+    tb::{TablebaseProber, Tablebases},
     threadpool::ThreadPool,
     time::{Limits, TimeManager},
-    transposition::TranspositionTable,
-    types::{MAX_MOVES, MAX_PLY, Move, Score, normalize_to_cp},
+    transposition::{Bound, Entry, PendingWrites, TranspositionTable},
+    types::{MAX_MOVES, MAX_PLY, Move, Score, normalize_to_cp, win_rate_model},
 };
 
 pub trait UciWriter: Send {
@@ -160,7 +163,27 @@ pub struct SharedContext {
     pub tb_hits: Counter,
     pub stop_probing_tb: AtomicBool,
     pub root_in_tb: AtomicBool,
+    pub tablebases: Tablebases,
+    pub syzygy_probe_depth: AtomicI32,
+    pub syzygy_probe_limit: AtomicUsize,
+    pub syzygy_50_move_rule: AtomicBool,
+    pub pondering: AtomicBool,
+    pub show_wdl: AtomicBool,
+    /// Prints PVs in SAN, set by the `san` command and cleared by `uci`.
+    pub print_san: AtomicBool,
+    pub currmove_delay: AtomicU64,
+    pub heartbeat_interval: AtomicU64,
     pub soft_stop_votes: AtomicUsize,
+    /// Draw score for each side to move, set before every search.
+    pub contempt: [AtomicI32; 2],
+    /// Read when the search data of the threads is created, which `setoption` redoes on a change.
+    pub deterministic: AtomicBool,
+    /// The thread allowed to merge its iteration in deterministic mode.
+    pub turn: AtomicUsize,
+    /// The arrivals of the threads at the synchronization points of a deterministic search.
+    pub arrivals: AtomicUsize,
+    /// Set while merging an iteration in deterministic mode if the search stops after it.
+    pub stop_after_merge: AtomicBool,
     pub best_stats: [AtomicU32; MAX_MOVES],
     pub history: Arc<NumaReplicated<SharedCorrectionHistory>>,
     pub parameters: Arc<NumaReplicated<ParametersHandle>>,
@@ -178,7 +201,21 @@ impl Default for SharedContext {
             tb_hits: Counter::default(),
             stop_probing_tb: AtomicBool::new(false),
             root_in_tb: AtomicBool::new(false),
+            tablebases: Tablebases::default(),
+            syzygy_probe_depth: AtomicI32::new(1),
+            syzygy_probe_limit: AtomicUsize::new(7),
+            syzygy_50_move_rule: AtomicBool::new(true),
+            pondering: AtomicBool::new(false),
+            show_wdl: AtomicBool::new(false),
+            print_san: AtomicBool::new(false),
+            currmove_delay: AtomicU64::new(3000),
+            heartbeat_interval: AtomicU64::new(1000),
             soft_stop_votes: AtomicUsize::new(0),
+            contempt: [const { AtomicI32::new(0) }; 2],
+            deterministic: AtomicBool::new(false),
+            turn: AtomicUsize::new(0),
+            arrivals: AtomicUsize::new(0),
+            stop_after_merge: AtomicBool::new(false),
             best_stats: [const { AtomicU32::new(0) }; MAX_MOVES],
             history: NumaReplicated::new(numa_context.clone()),
             parameters: NumaReplicated::new(numa_context.clone()),
@@ -227,6 +264,16 @@ pub struct ThreadData {
     pub continuation_corrhist: ContinuationCorrectionHistory,
     pub best_move_changes: usize,
     pub optimism: [i32; 2],
+    pub contempt: [i32; 2],
+    pub deterministic: bool,
+    /// The tablebase prober of the current search, so that probes in search take no lock.
+    pub tablebases: Arc<dyn TablebaseProber>,
+    /// Transposition table writes of the current iteration, kept apart in deterministic mode
+    /// when several threads search.
+    pub pending_writes: Option<PendingWrites>,
+    /// Set in deterministic mode when this thread has to stop. The others stop once they
+    /// have merged the current iteration.
+    pub halted: bool,
     pub root_depth: i32,
     pub root_delta: i32,
     pub sel_depth: i32,
@@ -240,19 +287,33 @@ pub struct ThreadData {
     pub cutoff_count: PlyArray<i32, { MAX_PLY + 16 }>,
     pub excluded: PlyArray<Move, { MAX_PLY + 16 }>,
     pub writer: Box<dyn UciWriter>,
+    pub report: Report,
+    pub last_info: Duration,
 }
 
 impl ThreadData {
-    pub fn new(shared: Arc<SharedContext>, numa_token: NumaReplicatedAccessToken) -> Self {
-        let corrhist = shared.history.get(numa_token);
+    /// Creates the search state of one of `thread_count` threads, or returns `None` if its tables
+    /// cannot be allocated.
+    pub fn try_new(
+        shared: Arc<SharedContext>, numa_token: NumaReplicatedAccessToken, thread_count: usize,
+    ) -> Option<Self> {
+        let deterministic = shared.deterministic.load(Ordering::Relaxed);
         let parameters = shared.parameters.get(numa_token);
+        let tablebases = shared.tablebases.prober();
 
-        Self {
+        // In deterministic mode several threads keep their correction history to themselves, and
+        // their transposition table writes until the end of each iteration. A single thread has
+        // nothing to race with, so it searches exactly as it would otherwise.
+        let isolated = deterministic && thread_count > 1;
+        let corrhist = if isolated { SharedCorrectionHistory::allocate() } else { shared.history.get(numa_token) };
+        let pending_writes = if isolated { Some(PendingWrites::try_new()?) } else { None };
+
+        Some(Self {
             id: 0,
             shared,
             corrhist,
             board: Board::starting_position(),
-            time_manager: TimeManager::new(Limits::Infinite, 0, 0),
+            time_manager: TimeManager::new(Limits::default(), 0, 0),
             stack: Stack::new(),
             nnue: Network::new(parameters),
             root_moves: Vec::new(),
@@ -260,10 +321,15 @@ impl ThreadData {
             noisy_history: NoisyHistory::default(),
             quiet_history: QuietHistory::default(),
             pawn_history: PawnHistory::default(),
-            continuation_history: ContinuationHistory::default(),
-            continuation_corrhist: ContinuationCorrectionHistory::default(),
+            continuation_history: ContinuationHistory::try_new()?,
+            continuation_corrhist: ContinuationCorrectionHistory::try_new()?,
             best_move_changes: 0,
             optimism: [0; 2],
+            contempt: [0; 2],
+            deterministic,
+            tablebases,
+            pending_writes,
+            halted: false,
             root_depth: 0,
             root_delta: 0,
             sel_depth: 0,
@@ -280,13 +346,38 @@ impl ThreadData {
             writer: Box::new(StdoutWriter),
             #[cfg(target_arch = "wasm32")]
             writer: Box::new(BufferWriter::default()),
-        }
+            report: Report::None,
+            last_info: Duration::ZERO,
+        })
     }
 
     pub fn nodes(&self) -> u64 {
         self.shared.nodes.get(self.id)
     }
 
+    /// Returns `true` once the search of this thread has to stop.
+    pub fn stopped(&self) -> bool {
+        self.halted || self.shared.status.get() == Status::STOPPED
+    }
+
+    pub fn tt_read(&self, hash: u64, halfmove_clock: u8, ply: isize) -> Option<Entry> {
+        match &self.pending_writes {
+            Some(pending) => pending.read(&self.shared.tt, hash, halfmove_clock, ply),
+            None => self.shared.tt.read(hash, halfmove_clock, ply),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn tt_write(
+        &mut self, hash: u64, depth: i32, raw_eval: i32, score: i32, bound: Bound, mv: Move, ply: isize, tt_pv: bool,
+        force: bool,
+    ) {
+        match &mut self.pending_writes {
+            Some(pending) => pending.write(hash, depth, raw_eval, score, bound, mv, ply, tt_pv, force),
+            None => self.shared.tt.write(hash, depth, raw_eval, score, bound, mv, ply, tt_pv, force),
+        }
+    }
+
     pub fn corrhist(&self) -> &SharedCorrectionHistory {
         &self.corrhist
     }
@@ -305,6 +396,8 @@ impl ThreadData {
         let nps = self.shared.nodes.aggregate() as f64 / elapsed.as_secs_f64();
         let ms = elapsed.as_millis();
 
+        self.last_info = elapsed;
+
         for pv_index in 0..self.multi_pv {
             let root_move = &self.root_moves[pv_index];
 
@@ -319,20 +412,18 @@ impl ThreadData {
 
             let mut upperbound = root_move.upperbound;
             let mut lowerbound = root_move.lowerbound;
-
-            if self.shared.root_in_tb.load(Ordering::Relaxed) {
-                // Cursed win/loss due to 50-move rule
-                let is_cursed = root_move.tb_rank > 0 && root_move.tb_rank < 900
-                    || root_move.tb_rank < 0 && root_move.tb_rank > -900;
-
-                if is_cursed {
-                    upperbound = false;
-                    lowerbound = false;
-                    score = 0;
-                } else if score.abs() <= Score::TB_WIN {
-                    upperbound = false;
-                    lowerbound = false;
-                    score = root_move.tb_score;
+            let mut pv = root_move.pv.line();
+
+            if self.shared.root_in_tb.load(Ordering::Relaxed)
+                && let Some((tb_score, tb_pv)) =
+                    root_move.tablebase_report(score, self.shared.syzygy_50_move_rule.load(Ordering::Relaxed))
+            {
+                upperbound = false;
+                lowerbound = false;
+                score = tb_score;
+
+                if !tb_pv.is_empty() {
+                    pv = tb_pv;
                 }
             }
 
@@ -356,6 +447,11 @@ impl ThreadData {
                 formatted_score.push_str(" lowerbound");
             }
 
+            if self.shared.show_wdl.load(Ordering::Relaxed) {
+                let wdl = win_rate_model(score, &self.board);
+                formatted_score.push_str(&format!(" wdl {} {} {}", wdl.win, wdl.draw, wdl.loss));
+            }
+
             let mut line = format!(
                 "info depth {depth} seldepth {} multipv {} score {formatted_score} nodes {} time {ms} nps {nps:.0} hashfull {} tbhits {} pv",
                 root_move.sel_depth,
@@ -365,15 +461,62 @@ impl ThreadData {
                 self.shared.tb_hits.aggregate(),
             );
 
-            line.push_str(&format!(" {}", root_move.mv.to_uci(&self.board)));
-            for mv in root_move.pv.line() {
-                line.push_str(&format!(" {}", mv.to_uci(&self.board)));
+            if self.shared.print_san.load(Ordering::Relaxed) {
+                let mut board = self.board.clone();
+                for &mv in std::iter::once(&root_move.mv).chain(pv) {
+                    line.push_str(&format!(" {}", mv.to_san(&board)));
+                    board.make_move(mv, &mut NullBoardObserver);
+                }
+            } else {
+                line.push_str(&format!(" {}", root_move.mv.to_uci(&self.board)));
+                for mv in pv {
+                    line.push_str(&format!(" {}", mv.to_uci(&self.board)));
+                }
             }
 
             self.writer.write_line(&line);
         }
     }
 
+    /// Reports the root move about to be searched, once the search has run for `currmove_delay` ms.
+    pub fn print_currmove(&mut self, mv: Move, number: usize) {
+        let elapsed = self.time_manager.elapsed();
+        if elapsed < Duration::from_millis(self.shared.currmove_delay.load(Ordering::Relaxed)) {
+            return;
+        }
+
+        self.last_info = elapsed;
+
+        let line =
+            format!("info depth {} currmove {} currmovenumber {number}", self.root_depth, mv.to_uci(&self.board));
+        self.writer.write_line(&line);
+    }
+
+    /// Reports search statistics if nothing has been printed for `heartbeat_interval` ms.
+    pub fn print_heartbeat(&mut self) {
+        let interval = self.shared.heartbeat_interval.load(Ordering::Relaxed);
+        if interval == 0 {
+            return;
+        }
+
+        let elapsed = self.time_manager.elapsed();
+        if elapsed < self.last_info + Duration::from_millis(interval) {
+            return;
+        }
+
+        self.last_info = elapsed;
+
+        let nodes = self.shared.nodes.aggregate();
+        let line = format!(
+            "info nodes {nodes} nps {:.0} hashfull {} tbhits {} time {}",
+            nodes as f64 / elapsed.as_secs_f64(),
+            self.shared.tt.hashfull(),
+            self.shared.tb_hits.aggregate(),
+            elapsed.as_millis(),
+        );
+        self.writer.write_line(&line);
+    }
+
     fn print_uci_no_move(&mut self) {
         if self.board.in_check() {
             self.writer.write_line("info depth 0 score mate 0");
@@ -396,6 +539,8 @@ pub struct RootMove {
     pub pv: RootPV,
     pub tb_rank: i32,
     pub tb_score: i32,
+    /// The tablebase line after this move, shown instead of the search PV at a tablebase root.
+    pub tb_pv: Vec<Move>,
 }
 
 impl Default for RootMove {
@@ -412,6 +557,24 @@ impl Default for RootMove {
             pv: RootPV::default(),
             tb_rank: 0,
             tb_score: 0,
+            tb_pv: Vec::new(),
+        }
+    }
+}
+
+impl RootMove {
+    /// The score and line reported for this move at a tablebase root, or `None` if the search
+    /// found a mate. Cursed wins and blessed losses are reported as draws under the fifty-move rule.
+    pub fn tablebase_report(&self, score: i32, rule50: bool) -> Option<(i32, &[Move])> {
+        // Cursed win/loss due to 50-move rule
+        let is_cursed = self.tb_rank > 0 && self.tb_rank < 900 || self.tb_rank < 0 && self.tb_rank > -900;
+
+        if is_cursed && rule50 {
+            Some((0, &[]))
+        } else if score.abs() <= Score::TB_WIN {
+            Some((self.tb_score, &self.tb_pv))
+        } else {
+            None
         }
     }
 }
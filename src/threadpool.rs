@@ -11,14 +11,14 @@ use std::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::JsValue;
 
-#[cfg(feature = "syzygy")]
-use crate::tb;
 use crate::{
     board::Board,
     numa::NumaReplicatedAccessToken,
     search::{self, Report},
+    tb,
     thread::{RootMove, SharedContext, Status, ThreadData},
     time::TimeManager,
+    types::Move,
 };
 
 pub struct ThreadPool {
@@ -40,22 +40,34 @@ impl ThreadPool {
         shared.numa_context.set_thread_count(1);
 
         let workers = make_worker_threads(1);
-        let data = make_thread_data(shared, &workers);
+        let data = make_thread_data(shared, &workers).expect("Failed to allocate the search data of a single thread");
 
         Self { workers, vector: data }
     }
 
+    /// Sets the number of search threads. If the per-thread data cannot be allocated,
+    /// the count is halved until it can; check `len` for the number actually started.
     pub fn set_count(&mut self, threads: usize) {
-        let threads = threads.clamp(1, Self::available_threads());
+        let mut threads = threads.clamp(1, Self::available_threads());
         let shared = self.vector[0].shared.clone();
 
-        shared.numa_context.set_thread_count(threads);
-
         self.workers.drain(..).for_each(WorkerThread::join);
-        self.workers = make_worker_threads(threads);
-
         std::mem::drop(self.vector.drain(..));
-        self.vector = make_thread_data(shared, &self.workers);
+
+        loop {
+            shared.numa_context.set_thread_count(threads);
+            self.workers = make_worker_threads(threads);
+
+            if let Some(vector) = make_thread_data(shared.clone(), &self.workers) {
+                self.vector = vector;
+                return;
+            }
+
+            assert!(threads > 1, "Failed to allocate the search data of a single thread");
+
+            self.workers.drain(..).for_each(WorkerThread::join);
+            threads /= 2;
+        }
     }
 
     pub fn main_thread(&mut self) -> &mut ThreadData {
@@ -76,18 +88,25 @@ impl ThreadPool {
         shared.numa_context.set_thread_count(self.workers.len());
 
         std::mem::drop(self.vector.drain(..));
-        self.vector = make_thread_data(shared, &self.workers);
+        self.vector = make_thread_data(shared, &self.workers).expect("Failed to reallocate the search data");
     }
 
     pub fn execute_searches(
-        &mut self, time_manager: TimeManager, report: Report, multi_pv: usize, board: &Board,
+        &mut self, mut time_manager: TimeManager, report: Report, multi_pv: usize, searchmoves: &[Move], board: &Board,
         shared: &Arc<SharedContext>,
     ) {
+        if self.vector[0].deterministic {
+            time_manager.share_node_limit(self.vector.len());
+        }
+
         shared.tt.increment_age();
 
         shared.nodes.reset();
         shared.tb_hits.reset();
         shared.soft_stop_votes.store(0, Ordering::Release);
+        shared.turn.store(0, Ordering::Release);
+        shared.arrivals.store(0, Ordering::Release);
+        shared.stop_after_merge.store(false, Ordering::Release);
         shared.status.set(Status::RUNNING);
         shared.best_stats.iter().for_each(|x| {
             x.store((self.main_thread().previous_best_score + 32768) as u32, Ordering::Release);
@@ -103,8 +122,7 @@ impl ThreadPool {
             {
                 let t1 = &mut self.vector[0];
                 t1.board = (*board).clone();
-                t1.root_moves =
-                    t1.board.generate_all_moves().iter().map(|v| RootMove { mv: v.mv, ..Default::default() }).collect();
+                t1.root_moves = make_root_moves(&t1.board, searchmoves);
                 t1.multi_pv = multi_pv;
                 t1.time_manager = time_manager.clone();
             }
@@ -156,14 +174,15 @@ impl ThreadPool {
             t1.shared.stop_probing_tb.store(false, Ordering::Relaxed);
 
             t1.board = (*board).clone();
-            t1.root_moves =
-                t1.board.generate_all_moves().iter().map(|v| RootMove { mv: v.mv, ..Default::default() }).collect();
+            t1.root_moves = make_root_moves(&t1.board, searchmoves);
 
-            #[cfg(feature = "syzygy")]
-            if t1.board.castling().raw() == 0 && t1.board.occupancies().popcount() <= tb::size() && !t1.board.is_draw(0)
+            if t1.board.castling().raw() == 0
+                && t1.board.occupancies().popcount() <= tb::cardinality(shared)
+                && !t1.board.is_draw(0)
             {
                 tb::rank_rootmoves(t1);
                 tb::normalize_draw_ranks(t1);
+                tb::extend_root_pvs(t1, multi_pv);
             }
 
             let tm = time_manager.clone();
@@ -203,6 +222,25 @@ impl ThreadPool {
     }
 }
 
+/// Builds the root move list, restricted to `searchmoves` unless it is empty. If none of the
+/// `searchmoves` is legal, they are ignored with an `info string` and every move is searched.
+fn make_root_moves(board: &Board, searchmoves: &[Move]) -> Vec<RootMove> {
+    let root_moves = board
+        .generate_all_moves()
+        .iter()
+        .filter(|entry| searchmoves.is_empty() || searchmoves.contains(&entry.mv))
+        .map(|entry| RootMove { mv: entry.mv, ..Default::default() })
+        .collect::<Vec<_>>();
+
+    if root_moves.is_empty() && !searchmoves.is_empty() {
+        let ignored = searchmoves.iter().map(|mv| mv.to_uci(board)).collect::<Vec<_>>().join(" ");
+        println!("info string Ignoring illegal searchmoves '{ignored}', searching every move");
+        return make_root_moves(board, &[]);
+    }
+
+    root_moves
+}
+
 impl Index<usize> for ThreadPool {
     type Output = ThreadData;
 
@@ -338,11 +376,12 @@ fn make_worker_threads(num_threads: usize) -> Vec<WorkerThread> {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn make_thread_data(shared: Arc<SharedContext>, worker_threads: &[WorkerThread]) -> Vec<ThreadData> {
-    std::thread::scope(|scope| -> Vec<ThreadData> {
+fn make_thread_data(shared: Arc<SharedContext>, worker_threads: &[WorkerThread]) -> Option<Vec<ThreadData>> {
+    std::thread::scope(|scope| -> Option<Vec<ThreadData>> {
         let cfg = shared.numa_context.get_numa_config();
         let should_bind = cfg.suggests_binding_threads(worker_threads.len());
         let numa_nodes = cfg.distribute_threads_among_numa_nodes(worker_threads.len());
+        let thread_count = worker_threads.len();
 
         let handles = worker_threads
             .iter()
@@ -359,7 +398,7 @@ fn make_thread_data(shared: Arc<SharedContext>, worker_threads: &[WorkerThread])
                         } else {
                             NumaReplicatedAccessToken::new(0)
                         };
-                        tx.send(Box::new(ThreadData::new(shared, token))).unwrap();
+                        tx.send(ThreadData::try_new(shared, token, thread_count).map(Box::new)).unwrap();
                     },
                     worker,
                 );
@@ -368,13 +407,16 @@ fn make_thread_data(shared: Arc<SharedContext>, worker_threads: &[WorkerThread])
             .collect::<Vec<_>>();
 
         let mut thread_data: Vec<ThreadData> = Vec::with_capacity(handles.len());
+        let mut complete = true;
         for (rx, handle) in handles {
-            let td = rx.recv().unwrap();
-            thread_data.push(*td);
+            match rx.recv().unwrap() {
+                Some(td) => thread_data.push(*td),
+                None => complete = false,
+            }
             handle.join();
         }
 
-        thread_data
+        complete.then_some(thread_data)
     })
 }
 
@@ -392,7 +434,70 @@ fn make_worker_threads(num_threads: usize) -> Vec<WorkerThread> {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn make_thread_data(shared: Arc<SharedContext>, worker_threads: &[WorkerThread]) -> Vec<ThreadData> {
+fn make_thread_data(shared: Arc<SharedContext>, worker_threads: &[WorkerThread]) -> Option<Vec<ThreadData>> {
     let token = NumaReplicatedAccessToken::new(0);
-    worker_threads.iter().map(|_| ThreadData::new(shared.clone(), token)).collect()
+    worker_threads.iter().map(|_| ThreadData::try_new(shared.clone(), token, worker_threads.len())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::NullBoardObserver, lookup, time::Limits};
+
+    const NODES: u64 = 30_000;
+
+    /// Starts a fresh engine in deterministic mode and runs node-limited searches from a few
+    /// positions one after another, keeping what each search leaves behind for the next, as a
+    /// game would. Returns the best move, PV and node count of every thread after each search.
+    fn deterministic_searches(thread_count: usize) -> Vec<(Move, Vec<Move>, u64)> {
+        lookup::initialize();
+
+        let shared = Arc::new(SharedContext::default());
+        shared.deterministic.store(true, Ordering::Relaxed);
+
+        let mut threads = ThreadPool::new(shared.clone());
+        threads.set_count(thread_count);
+
+        let mut results = Vec::new();
+        for fen in [
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let limits = Limits { nodes: Some(NODES), ..Default::default() };
+            let time_manager = TimeManager::new(limits, board.fullmove_number(), 0);
+            threads.execute_searches(time_manager, Report::None, 1, &[], &board, &shared);
+
+            assert!(shared.nodes.aggregate() <= NODES + thread_count as u64);
+
+            for td in threads.iter() {
+                let root_move = &td.root_moves[0];
+                results.push((root_move.mv, root_move.pv.line().to_vec(), td.nodes()));
+            }
+        }
+        results
+    }
+
+    #[test]
+    fn illegal_searchmoves_are_ignored() {
+        lookup::initialize();
+
+        let board = Board::starting_position();
+        let mut black = board.clone();
+        black.make_move(board.find_uci_move("e2e4").unwrap(), &mut NullBoardObserver);
+        let illegal = black.find_uci_move("e7e5").unwrap();
+
+        assert_eq!(make_root_moves(&board, &[illegal]).len(), 20);
+        assert_eq!(make_root_moves(&board, &[illegal, board.find_uci_move("d2d4").unwrap()]).len(), 1);
+    }
+
+    #[test]
+    fn deterministic_single_thread() {
+        assert_eq!(deterministic_searches(1), deterministic_searches(1));
+    }
+
+    #[test]
+    fn deterministic_threads() {
+        assert_eq!(deterministic_searches(3), deterministic_searches(3));
+    }
 }
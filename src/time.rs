@@ -1,20 +1,50 @@
-use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+use std::{cell::Cell, sync::atomic::Ordering, time::Duration};
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
 use crate::thread::ThreadData;
 
-#[derive(Clone, Debug)]
-pub enum Limits {
-    Infinite,
-    Depth(i32),
-    Time(u64),
-    Nodes(u64),
-    Mate(u64),
-    Fischer(u64, u64),
-    Cyclic(u64, u64, u64),
+/// Search limits of a single `go` command. Any combination may be active at once,
+/// and the search stops at whichever limit is hit first.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub mate: Option<u64>,
+    pub clock: Option<Clock>,
+    pub infinite: bool,
+    pub ponder: bool,
+}
+
+/// The remaining time of the side to move, as sent by `wtime`/`btime`, `winc`/`binc` and `movestogo`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Clock {
+    pub time: u64,
+    pub increment: u64,
+    pub moves_to_go: Option<u64>,
+}
+
+impl Limits {
+    /// Returns `true` if any limit of elapsed time applies.
+    pub const fn is_timed(&self) -> bool {
+        self.movetime.is_some() || self.clock.is_some()
+    }
+
+    /// Takes the milliseconds and nodes already spent on the move off the limits.
+    pub fn deduct(&mut self, elapsed: u64, nodes: u64) {
+        if let Some(movetime) = &mut self.movetime {
+            *movetime = movetime.saturating_sub(elapsed);
+        }
+        if let Some(clock) = &mut self.clock {
+            clock.time = clock.time.saturating_sub(elapsed);
+        }
+        if let Some(maximum) = &mut self.nodes {
+            *maximum = maximum.saturating_sub(nodes).max(1);
+        }
+    }
 }
 
 const TIME_OVERHEAD_MS: u64 = 15;
@@ -23,81 +53,230 @@ const TIME_OVERHEAD_MS: u64 = 15;
 pub struct TimeManager {
     limits: Limits,
     start_time: Instant,
+    clock_start: Cell<Instant>,
     soft_bound: Duration,
     hard_bound: Duration,
+    pondering: Cell<bool>,
 }
 
 impl TimeManager {
     pub fn new(limits: Limits, fullmove_number: usize, move_overhead: u64) -> Self {
-        let soft;
-        let hard;
+        let mut soft = u64::MAX;
+        let mut hard = u64::MAX;
 
-        match limits {
-            Limits::Time(ms) => {
-                soft = ms;
-                hard = ms;
-            }
-            Limits::Fischer(main, inc) => {
+        if let Some(ms) = limits.movetime {
+            soft = ms;
+            hard = ms;
+        }
+
+        match limits.clock {
+            Some(Clock { time, increment, moves_to_go: None }) => {
                 let soft_scale = 0.0594 - 0.0492 * (-0.0386 * fullmove_number as f64).exp();
                 let hard_scale = 0.7281;
 
-                let soft_bound = (soft_scale * main.saturating_sub(move_overhead) as f64 + 0.75 * inc as f64) as u64;
-                let hard_bound = (hard_scale * main.saturating_sub(move_overhead) as f64 + 0.75 * inc as f64) as u64;
+                let soft_bound =
+                    (soft_scale * time.saturating_sub(move_overhead) as f64 + 0.75 * increment as f64) as u64;
+                let hard_bound =
+                    (hard_scale * time.saturating_sub(move_overhead) as f64 + 0.75 * increment as f64) as u64;
 
-                soft = soft_bound.min(main.saturating_sub(move_overhead));
-                hard = hard_bound.min(main.saturating_sub(move_overhead));
+                soft = soft.min(soft_bound.min(time.saturating_sub(move_overhead)));
+                hard = hard.min(hard_bound.min(time.saturating_sub(move_overhead)));
             }
-            Limits::Cyclic(main, inc, moves) => {
-                let main = main.saturating_sub(move_overhead);
-                let base = (main as f64 / moves as f64) + 0.75 * inc as f64;
+            Some(Clock { time, increment, moves_to_go: Some(moves) }) => {
+                let time = time.saturating_sub(move_overhead);
+                let base = (time as f64 / moves.max(1) as f64) + 0.75 * increment as f64;
 
-                soft = ((1.0 * base) as u64).min(main + inc);
-                hard = ((5.0 * base) as u64).min(main + inc);
-            }
-            _ => {
-                soft = u64::MAX;
-                hard = u64::MAX;
+                soft = soft.min(((1.0 * base) as u64).min(time + increment));
+                hard = hard.min(((5.0 * base) as u64).min(time + increment));
             }
+            None => (),
         }
 
+        let now = Instant::now();
+
         Self {
+            pondering: Cell::new(limits.ponder),
             limits,
-            start_time: Instant::now(),
+            start_time: now,
+            clock_start: Cell::new(now),
             soft_bound: Duration::from_millis(soft.saturating_sub(TIME_OVERHEAD_MS)),
             hard_bound: Duration::from_millis(hard.saturating_sub(TIME_OVERHEAD_MS)),
         }
     }
 
+    /// Returns `true` while waiting for `ponderhit`. The first call after the hit
+    /// restarts the clock, turning the saved limits into a normal timed search.
+    pub fn is_pondering(&self, td: &ThreadData) -> bool {
+        if !self.pondering.get() {
+            return false;
+        }
+
+        if td.shared.pondering.load(Ordering::Acquire) {
+            return true;
+        }
+
+        self.pondering.set(false);
+        self.clock_start.set(Instant::now());
+        false
+    }
+
+    /// Returns `true` once the hard bound derived from `movetime` and the clock has passed.
+    /// Pondering is not taken into account.
+    pub fn hard_bound_passed(&self) -> bool {
+        self.clock_start.get().elapsed() >= self.hard_bound
+    }
+
+    pub const fn hard_bound(&self) -> Duration {
+        self.hard_bound
+    }
+
     pub fn elapsed(&self) -> Duration {
         self.start_time.elapsed()
     }
 
     pub fn soft_limit(&self, td: &ThreadData, multiplier: impl Fn() -> f32) -> bool {
-        match self.limits {
-            Limits::Infinite | Limits::Depth(_) | Limits::Mate(_) => false,
-            Limits::Nodes(maximum) => td.shared.nodes.aggregate() >= maximum,
-            Limits::Time(maximum) => self.start_time.elapsed() >= Duration::from_millis(maximum),
-            _ => self.start_time.elapsed() >= Duration::from_secs_f32(self.soft_bound.as_secs_f32() * multiplier()),
+        if self.is_pondering(td) {
+            return false;
         }
+
+        let elapsed = self.clock_start.get().elapsed();
+
+        self.limits.nodes.is_some_and(|maximum| searched_nodes(td) >= maximum)
+            || self.limits.movetime.is_some_and(|maximum| elapsed >= Duration::from_millis(maximum))
+            || (self.limits.clock.is_some()
+                && elapsed >= Duration::from_secs_f32(self.soft_bound.as_secs_f32() * multiplier()))
     }
 
     pub fn check_time(&self, td: &ThreadData) -> bool {
-        if td.completed_depth == 0 {
+        if td.completed_depth == 0 || self.is_pondering(td) {
             return false;
         }
 
-        match self.limits {
-            Limits::Infinite | Limits::Depth(_) | Limits::Mate(_) => false,
-            Limits::Nodes(maximum) => td.shared.nodes.aggregate() > maximum,
-            _ => td.nodes() & 2047 == 2047 && self.start_time.elapsed() >= self.hard_bound,
+        self.limits.nodes.is_some_and(|maximum| searched_nodes(td) > maximum)
+            || (self.limits.is_timed()
+                && td.nodes() & 2047 == 2047
+                && self.clock_start.get().elapsed() >= self.hard_bound)
+    }
+
+    /// Gives each of `threads` threads an equal share of the node limit. Used in deterministic
+    /// mode, where the threads only count their own nodes, see `searched_nodes`.
+    pub fn share_node_limit(&mut self, threads: usize) {
+        if let Some(nodes) = &mut self.limits.nodes {
+            *nodes = (*nodes / threads as u64).max(1);
         }
     }
 
-    pub fn limits(&self) -> Limits {
-        self.limits.clone()
+    pub const fn limits(&self) -> &Limits {
+        &self.limits
     }
 
-    pub fn use_time_management(&self) -> bool {
-        matches!(self.limits, Limits::Fischer(..) | Limits::Cyclic(..) | Limits::Time(_))
+    pub const fn use_time_management(&self) -> bool {
+        self.limits.is_timed()
+    }
+}
+
+/// The nodes counted against the node limit: those of all threads, or in deterministic mode only
+/// those of the thread itself, which do not depend on how far the others have got.
+fn searched_nodes(td: &ThreadData) -> u64 {
+    if td.deterministic { td.nodes() } else { td.shared.nodes.aggregate() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{board::Board, lookup, search::Report, thread::SharedContext, threadpool::ThreadPool};
+
+    fn search(limits: Limits) -> (i32, u64, Duration) {
+        lookup::initialize();
+
+        let shared = Arc::new(SharedContext::default());
+        let mut pool = ThreadPool::new(shared.clone());
+        let board = Board::starting_position();
+
+        let start = Instant::now();
+        pool.execute_searches(TimeManager::new(limits, 0, 0), Report::None, 1, &[], &board, &shared);
+
+        (pool[0].completed_depth, shared.nodes.aggregate(), start.elapsed())
+    }
+
+    #[test]
+    fn bounds_take_the_tightest_limit() {
+        let clock = Clock { time: 60_000, increment: 0, moves_to_go: None };
+        let limits = Limits {
+            movetime: Some(500),
+            clock: Some(clock),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, 0, 0);
+
+        assert_eq!(tm.hard_bound, Duration::from_millis(500 - TIME_OVERHEAD_MS));
+        assert!(tm.soft_bound <= tm.hard_bound);
+
+        let limits = Limits {
+            movetime: Some(100_000),
+            clock: Some(clock),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, 0, 0);
+
+        assert!(tm.hard_bound < Duration::from_millis(60_000));
+    }
+
+    #[test]
+    fn deducted_limits_keep_the_rest() {
+        let mut limits = Limits {
+            nodes: Some(1000),
+            movetime: Some(500),
+            clock: Some(Clock { time: 60_000, increment: 100, moves_to_go: None }),
+            ..Default::default()
+        };
+
+        limits.deduct(200, 400);
+        assert_eq!((limits.nodes, limits.movetime), (Some(600), Some(300)));
+        assert_eq!(limits.clock.map(|clock| (clock.time, clock.increment)), Some((59_800, 100)));
+
+        limits.deduct(1000, 1000);
+        assert_eq!((limits.nodes, limits.movetime), (Some(1), Some(0)));
+    }
+
+    #[test]
+    fn depth_stops_before_movetime() {
+        let (depth, _, elapsed) = search(Limits { depth: Some(3), movetime: Some(60_000), ..Default::default() });
+
+        assert_eq!(depth, 3);
+        assert!(elapsed < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn nodes_stop_before_depth() {
+        let (depth, nodes, _) = search(Limits { depth: Some(200), nodes: Some(20_000), ..Default::default() });
+
+        assert!(depth < 200);
+        assert!(nodes <= 20_000 + 1);
+    }
+
+    #[test]
+    fn movetime_stops_before_depth_and_nodes() {
+        let limits = Limits {
+            depth: Some(200),
+            nodes: Some(u64::MAX / 2),
+            movetime: Some(200),
+            ..Default::default()
+        };
+        let (depth, _, elapsed) = search(limits);
+
+        assert!(depth < 200);
+        assert!(elapsed < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn clock_stops_before_depth() {
+        let clock = Clock { time: 1000, increment: 0, moves_to_go: None };
+        let (depth, _, elapsed) = search(Limits { depth: Some(200), clock: Some(clock), ..Default::default() });
+
+        assert!(depth < 200);
+        assert!(elapsed < Duration::from_secs(5));
     }
 }
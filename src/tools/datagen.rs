@@ -0,0 +1,480 @@
+//! Generates NNUE training data from self-play games.
+//!
+//! Every game starts from a standard, Chess960 or double Chess960 (DFRC) setup,
+//! followed by a number of uniformly random plies. Openings whose first search
+//! score exceeds the eval bound are discarded. The rest of the game is played
+//! with a fixed node or depth limit per move, and quiet positions are written
+//! together with their search score and the final game result.
+//!
+//! Positions are stored in the 32-byte marlinformat used by bullet and marlinflow.
+//!
+//! Usage: `datagen [output <file>] [games <n>] [threads <n>] [nodes <n> | depth <n>] [seed <n>]
+//! [mode standard|chess960|dfrc] [plies <n>] [bound <cp>] [hash <mb>]`
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{Board, NullBoardObserver},
+    misc::SplitMix64,
+    search::Report,
+    thread::SharedContext,
+    threadpool::ThreadPool,
+    time::{Limits, TimeManager},
+    types::{CastlingKind, Color, Move, PieceType, Score, Square, is_decisive, normalize_to_cp},
+};
+
+const DEFAULT_OUTPUT: &str = "data.bin";
+const DEFAULT_GAMES: u64 = 1000;
+const DEFAULT_THREADS: usize = 1;
+const DEFAULT_NODES: u64 = 5000;
+const DEFAULT_PLIES: usize = 8;
+const DEFAULT_BOUND: i32 = 400;
+const DEFAULT_HASH: usize = 16;
+
+/// A side is declared the winner once the score stays above this many centipawns...
+const WIN_SCORE: i32 = 2000;
+/// ...for this many consecutive plies.
+const WIN_PLIES: usize = 6;
+
+/// A game is declared drawn once, after `DRAW_MIN_PLY`, the score stays within
+/// `DRAW_SCORE` centipawns for `DRAW_PLIES` consecutive plies.
+const DRAW_MIN_PLY: usize = 80;
+const DRAW_SCORE: i32 = 10;
+const DRAW_PLIES: usize = 10;
+
+const MAX_GAME_PLIES: usize = 600;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Standard,
+    Chess960,
+    Dfrc,
+}
+
+#[derive(Clone)]
+struct Config {
+    output: String,
+    games: u64,
+    threads: usize,
+    limits: Limits,
+    seed: u64,
+    mode: Mode,
+    plies: usize,
+    bound: i32,
+    hash: usize,
+}
+
+/// A recorded position with the search score from White's point of view.
+struct Sample {
+    board: Board,
+    score: i32,
+}
+
+pub fn datagen(args: &[&str]) {
+    let mut config = Config {
+        output: DEFAULT_OUTPUT.to_string(),
+        games: DEFAULT_GAMES,
+        threads: DEFAULT_THREADS,
+        limits: Limits::default(),
+        seed: 0,
+        mode: Mode::Standard,
+        plies: DEFAULT_PLIES,
+        bound: DEFAULT_BOUND,
+        hash: DEFAULT_HASH,
+    };
+
+    for pair in args.chunks(2) {
+        match pair {
+            ["output", v] => config.output = v.to_string(),
+            ["games", v] => config.games = v.parse().unwrap_or(DEFAULT_GAMES),
+            ["threads", v] => config.threads = v.parse().unwrap_or(DEFAULT_THREADS).max(1),
+            ["nodes", v] => config.limits.nodes = v.parse().ok(),
+            ["depth", v] => config.limits.depth = v.parse().ok(),
+            ["seed", v] => config.seed = v.parse().unwrap_or_default(),
+            ["mode", "standard"] => config.mode = Mode::Standard,
+            ["mode", "chess960"] => config.mode = Mode::Chess960,
+            ["mode", "dfrc"] => config.mode = Mode::Dfrc,
+            ["plies", v] => config.plies = v.parse().unwrap_or(DEFAULT_PLIES),
+            ["bound", v] => config.bound = v.parse().unwrap_or(DEFAULT_BOUND),
+            ["hash", v] => config.hash = v.parse().unwrap_or(DEFAULT_HASH),
+            _ => eprintln!("Ignoring unknown argument: '{}'", pair.join(" ")),
+        }
+    }
+
+    if config.limits.nodes.is_none() && config.limits.depth.is_none() {
+        config.limits.nodes = Some(DEFAULT_NODES);
+    }
+
+    let file = match File::create(&config.output) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to create '{}': {e}", config.output);
+            return;
+        }
+    };
+
+    let writer = Mutex::new(BufWriter::new(file));
+    let started = AtomicU64::new(0);
+    let finished = AtomicU64::new(0);
+    let positions = AtomicU64::new(0);
+    let time = Instant::now();
+
+    std::thread::scope(|scope| {
+        for worker in 0..config.threads {
+            let config = config.clone();
+            let (writer, started, finished, positions) = (&writer, &started, &finished, &positions);
+
+            scope.spawn(move || {
+                let mut rng =
+                    SplitMix64::new(config.seed.wrapping_add(worker as u64).wrapping_mul(0x2545_F491_4F6C_DD1D));
+
+                let shared = Arc::new(SharedContext::default());
+                shared.tt.resize(1, config.hash);
+
+                let mut pool = ThreadPool::new(shared.clone());
+
+                while started.fetch_add(1, Ordering::Relaxed) < config.games {
+                    let (samples, result) = play_game(&config, &mut rng, &mut pool, &shared);
+
+                    let mut buffer = Vec::with_capacity(samples.len() * 32);
+                    for sample in &samples {
+                        buffer.extend_from_slice(&pack(&sample.board, sample.score, result));
+                    }
+
+                    writer.lock().unwrap().write_all(&buffer).expect("Failed to write training data");
+
+                    positions.fetch_add(samples.len() as u64, Ordering::Relaxed);
+                    finished.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+
+        let mut last_report = Instant::now();
+        while finished.load(Ordering::Relaxed) < config.games {
+            std::thread::sleep(Duration::from_millis(100));
+
+            if last_report.elapsed() >= Duration::from_secs(10) {
+                last_report = Instant::now();
+                report(finished.load(Ordering::Relaxed), positions.load(Ordering::Relaxed), time);
+            }
+        }
+    });
+
+    if let Err(e) = writer.lock().unwrap().flush() {
+        eprintln!("Failed to flush '{}': {e}", config.output);
+    }
+
+    report(finished.load(Ordering::Relaxed), positions.load(Ordering::Relaxed), time);
+}
+
+fn report(games: u64, positions: u64, time: Instant) {
+    let seconds = time.elapsed().as_secs_f64();
+    println!("Games {games:>8} | Positions {positions:>10} | {:>8.0} pos/s", positions as f64 / seconds);
+}
+
+/// Plays a single game and returns the recorded positions together with the
+/// result from White's point of view (0 = loss, 1 = draw, 2 = win).
+fn play_game(
+    config: &Config, rng: &mut SplitMix64, pool: &mut ThreadPool, shared: &Arc<SharedContext>,
+) -> (Vec<Sample>, u8) {
+    'game: loop {
+        let Some(mut board) = random_opening(config, rng) else { continue };
+
+        pool.clear();
+        shared.tt.clear(1);
+
+        let mut samples = Vec::new();
+        let mut adjudicator = Adjudicator::default();
+
+        for ply in 0.. {
+            if !board.has_legal_moves() {
+                let result = match board.in_check() {
+                    true if board.side_to_move() == Color::White => 0,
+                    true => 2,
+                    false => 1,
+                };
+                return (samples, result);
+            }
+
+            if board.is_draw(0) || ply >= MAX_GAME_PLIES {
+                return (samples, 1);
+            }
+
+            if let Some(result) = probe_tablebases(&board, shared) {
+                return (samples, result);
+            }
+
+            let time_manager = TimeManager::new(config.limits.clone(), board.fullmove_number(), 0);
+            pool.execute_searches(time_manager, Report::None, 1, &[], &board, shared);
+
+            let root_move = &pool.main_thread().root_moves[0];
+            let (mv, score) = (root_move.mv, root_move.score);
+
+            let white_score = if board.side_to_move() == Color::White { score } else { -score };
+            let cp = if is_decisive(score) { white_score } else { normalize_to_cp(white_score, &board) };
+
+            if ply == 0 && cp.abs() > config.bound {
+                // The opening is too unbalanced, start over with a new one
+                continue 'game;
+            }
+
+            if let Some(result) = adjudicator.update(ply, cp) {
+                return (samples, result);
+            }
+
+            if is_quiet_sample(&board, mv, score) {
+                samples.push(Sample { board: board.clone(), score: cp });
+            }
+
+            board.make_move(mv, &mut NullBoardObserver);
+        }
+    }
+}
+
+/// Tracks the scores of a game to end it early once the result is clear.
+#[derive(Default)]
+struct Adjudicator {
+    win_plies: usize,
+    win_sign: i32,
+    draw_plies: usize,
+}
+
+impl Adjudicator {
+    /// Records the score in centipawns from White's point of view after `ply` plies, and
+    /// returns the result of the game if it can be adjudicated.
+    fn update(&mut self, ply: usize, cp: i32) -> Option<u8> {
+        let sign = if cp.abs() >= WIN_SCORE { cp.signum() } else { 0 };
+        self.win_plies = if sign != 0 && sign == self.win_sign { self.win_plies + 1 } else { (sign != 0) as usize };
+        self.win_sign = sign;
+
+        if self.win_plies >= WIN_PLIES {
+            return Some(if self.win_sign > 0 { 2 } else { 0 });
+        }
+
+        self.draw_plies = if cp.abs() <= DRAW_SCORE { self.draw_plies + 1 } else { 0 };
+        if ply >= DRAW_MIN_PLY && self.draw_plies >= DRAW_PLIES {
+            return Some(1);
+        }
+
+        None
+    }
+}
+
+/// Only quiet positions are useful targets for a static evaluation.
+fn is_quiet_sample(board: &Board, mv: Move, score: i32) -> bool {
+    !board.in_check() && !mv.is_noisy() && !is_decisive(score) && score != -Score::INFINITE
+}
+
+fn probe_tablebases(board: &Board, shared: &SharedContext) -> Option<u8> {
+    use crate::tb::GameOutcome;
+
+    if board.occupancies().popcount() > shared.tablebases.size()
+        || board.fiftymove_clock() != 0
+        || board.castling().raw() != 0
+    {
+        return None;
+    }
+
+    let white = board.side_to_move() == Color::White;
+    match shared.tablebases.probe(board, true)? {
+        GameOutcome::Win => Some(if white { 2 } else { 0 }),
+        GameOutcome::Loss => Some(if white { 0 } else { 2 }),
+        GameOutcome::Draw => Some(1),
+    }
+}
+
+/// Builds a starting position and plays random plies from it. Returns `None`
+/// if the game ended during the random plies.
+fn random_opening(config: &Config, rng: &mut SplitMix64) -> Option<Board> {
+    let fen = match config.mode {
+        Mode::Standard => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+        Mode::Chess960 => {
+            let white = chess960_back_rank(rng.next() % 960);
+            start_fen(white, white)
+        }
+        Mode::Dfrc => start_fen(chess960_back_rank(rng.next() % 960), chess960_back_rank(rng.next() % 960)),
+    };
+
+    let mut board = Board::from_fen(&fen).unwrap();
+    board.set_frc(config.mode != Mode::Standard);
+
+    for _ in 0..config.plies {
+        let moves = board.generate_all_moves();
+        if moves.is_empty() {
+            return None;
+        }
+
+        let mv = moves[(rng.next() % moves.len() as u64) as usize].mv;
+        board.make_move(mv, &mut NullBoardObserver);
+    }
+
+    board.has_legal_moves().then_some(board)
+}
+
+/// Returns the back rank of the Chess960 position with the given Scharnagl number
+/// (518 is the standard setup).
+fn chess960_back_rank(index: u64) -> [PieceType; 8] {
+    const KNIGHTS: [(usize, usize); 10] =
+        [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+    let mut rank = [PieceType::None; 8];
+    let empty = |rank: &[PieceType; 8]| (0..8).filter(|&file| rank[file] == PieceType::None).collect::<Vec<_>>();
+
+    rank[(index % 4 * 2 + 1) as usize] = PieceType::Bishop;
+    rank[(index / 4 % 4 * 2) as usize] = PieceType::Bishop;
+
+    let queen = empty(&rank)[(index / 16 % 6) as usize];
+    rank[queen] = PieceType::Queen;
+
+    let squares = empty(&rank);
+    let (first, second) = KNIGHTS[(index / 96) as usize];
+    rank[squares[first]] = PieceType::Knight;
+    rank[squares[second]] = PieceType::Knight;
+
+    let squares = empty(&rank);
+    rank[squares[0]] = PieceType::Rook;
+    rank[squares[1]] = PieceType::King;
+    rank[squares[2]] = PieceType::Rook;
+
+    rank
+}
+
+/// Formats a FEN with both sides' back ranks and Shredder-FEN castling rights.
+fn start_fen(white: [PieceType; 8], black: [PieceType; 8]) -> String {
+    let row =
+        |rank: &[PieceType; 8]| rank.iter().map(|&pt| "pnbrqk".as_bytes()[pt as usize] as char).collect::<String>();
+    let rooks = |rank: &[PieceType; 8]| {
+        (0..8)
+            .rev()
+            .filter(|&file| rank[file] == PieceType::Rook)
+            .map(|file| (b'a' + file as u8) as char)
+            .collect::<String>()
+    };
+
+    let castling = rooks(&white).to_uppercase() + &rooks(&black);
+
+    format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {castling} - 0 1", row(&black), row(&white).to_uppercase())
+}
+
+/// Encodes a position in marlinformat: occupancy, packed pieces, side to move and
+/// en passant square, halfmove clock, fullmove number, score, result and a spare byte.
+fn pack(board: &Board, score: i32, result: u8) -> [u8; 32] {
+    let mut unmoved_rooks = 0u64;
+    for (kind, king_to) in [
+        (CastlingKind::WhiteKingside, Square::G1),
+        (CastlingKind::WhiteQueenside, Square::C1),
+        (CastlingKind::BlackKingside, Square::G8),
+        (CastlingKind::BlackQueenside, Square::C8),
+    ] {
+        if board.castling().is_allowed(kind) {
+            unmoved_rooks |= 1 << board.get_castling_rook(king_to).0 as u64;
+        }
+    }
+
+    let mut bytes = [0; 32];
+    bytes[0..8].copy_from_slice(&board.occupancies().0.to_le_bytes());
+
+    for (index, square) in board.occupancies().into_iter().enumerate() {
+        let piece = board.piece_on(square);
+        let piece_type = if unmoved_rooks & (1 << square as u64) != 0 { 6 } else { piece.piece_type() as u8 };
+        let nibble = piece_type | ((piece.color() == Color::Black) as u8) << 3;
+
+        bytes[8 + index / 2] |= nibble << (4 * (index % 2));
+    }
+
+    let ep = if board.en_passant() == Square::None { 64 } else { board.en_passant() as u8 };
+    bytes[24] = ep | ((board.side_to_move() == Color::Black) as u8) << 7;
+    bytes[25] = board.fiftymove_clock();
+    bytes[26..28].copy_from_slice(&(board.fullmove_number() as u16).to_le_bytes());
+    bytes[28..30].copy_from_slice(&(score.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes());
+    bytes[30] = result;
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn back_rank(index: u64) -> String {
+        chess960_back_rank(index).iter().map(|&pt| "PNBRQK".as_bytes()[pt as usize] as char).collect()
+    }
+
+    #[test]
+    fn chess960_back_ranks() {
+        assert_eq!(back_rank(0), "BBQNNRKR");
+        assert_eq!(back_rank(518), "RNBQKBNR");
+        assert_eq!(back_rank(959), "RKRNNQBB");
+    }
+
+    #[test]
+    fn pack_layout() {
+        let bytes = pack(&Board::starting_position(), -50, 1);
+
+        assert_eq!(bytes[0..8], 0xFFFF_0000_0000_FFFFu64.to_le_bytes());
+
+        // White's back rank, with the castling rooks marked as unmoved (6).
+        assert_eq!(bytes[8..12], [0x16, 0x42, 0x25, 0x61]);
+        // Black's back rank has the colour bit set on every piece.
+        assert_eq!(bytes[20..24], [0x9E, 0xCA, 0xAD, 0xE9]);
+
+        assert_eq!(bytes[24], 64);
+        assert_eq!(bytes[25], 0);
+        assert_eq!(bytes[26..28], 1u16.to_le_bytes());
+        assert_eq!(bytes[28..30], (-50i16).to_le_bytes());
+        assert_eq!(bytes[30..32], [1, 0]);
+    }
+
+    #[test]
+    fn pack_side_to_move_and_clamped_score() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K2R b K - 7 30").unwrap();
+        let bytes = pack(&board, 100_000, 2);
+
+        assert_eq!(bytes[8..10], [0x65, 0x0D]);
+        assert_eq!(bytes[24], 64 | 0x80);
+        assert_eq!(bytes[25], 7);
+        assert_eq!(bytes[26..28], 30u16.to_le_bytes());
+        assert_eq!(bytes[28..30], i16::MAX.to_le_bytes());
+        assert_eq!(bytes[30], 2);
+    }
+
+    #[test]
+    fn adjudicates_wins_after_consecutive_plies() {
+        let mut adjudicator = Adjudicator::default();
+        for ply in 0..WIN_PLIES - 1 {
+            assert_eq!(adjudicator.update(ply, WIN_SCORE), None);
+        }
+        assert_eq!(adjudicator.update(WIN_PLIES, WIN_SCORE), Some(2));
+
+        let mut adjudicator = Adjudicator::default();
+        for ply in 0..WIN_PLIES - 1 {
+            assert_eq!(adjudicator.update(ply, -WIN_SCORE), None);
+        }
+        // A single score below the threshold restarts the count.
+        assert_eq!(adjudicator.update(WIN_PLIES, -WIN_SCORE + 1), None);
+        assert_eq!(adjudicator.update(WIN_PLIES + 1, -WIN_SCORE), None);
+    }
+
+    #[test]
+    fn adjudicates_draws_only_late_in_the_game() {
+        let mut adjudicator = Adjudicator::default();
+        for ply in 0..DRAW_MIN_PLY {
+            assert_eq!(adjudicator.update(ply, DRAW_SCORE), None);
+        }
+        assert_eq!(adjudicator.update(DRAW_MIN_PLY, DRAW_SCORE), Some(1));
+
+        let mut adjudicator = Adjudicator::default();
+        for ply in DRAW_MIN_PLY..DRAW_MIN_PLY + DRAW_PLIES - 1 {
+            assert_eq!(adjudicator.update(ply, -DRAW_SCORE), None);
+        }
+        assert_eq!(adjudicator.update(DRAW_MIN_PLY + DRAW_PLIES, DRAW_SCORE + 1), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! Runs an EPD test suite (WAC, STS, ERET and the like) and reports how many
+//! positions were solved. A position is solved when the final best move is one
+//! of the `bm` moves and none of the `am` moves.
+//!
+//! Usage: `epd <file> [depth <n> | nodes <n> | movetime <ms>] [threads <n>] [hash <mb>]`
+
+use std::{sync::Arc, time::Instant};
+
+use crate::{
+    board::Board,
+    search::Report,
+    thread::{BufferWriter, SharedContext},
+    threadpool::ThreadPool,
+    time::{Limits, TimeManager},
+    types::Move,
+};
+
+const DEFAULT_MOVETIME: u64 = 1000;
+const DEFAULT_HASH: usize = 16;
+const DEFAULT_THREADS: usize = 1;
+
+struct Entry {
+    id: String,
+    fen: String,
+    best: Vec<Move>,
+    avoid: Vec<Move>,
+}
+
+impl Entry {
+    fn is_solved_by(&self, mv: Move) -> bool {
+        (self.best.is_empty() || self.best.contains(&mv)) && !self.avoid.contains(&mv)
+    }
+}
+
+pub fn epd(args: &[&str]) {
+    let Some((path, args)) = args.split_first() else {
+        eprintln!("Usage: epd <file> [depth <n> | nodes <n> | movetime <ms>] [threads <n>] [hash <mb>]");
+        return;
+    };
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read '{path}': {e}");
+            return;
+        }
+    };
+
+    let mut limits = Limits::default();
+    let mut threads = DEFAULT_THREADS;
+    let mut hash = DEFAULT_HASH;
+
+    for pair in args.chunks(2) {
+        match pair {
+            ["depth", v] => limits.depth = v.parse().ok(),
+            ["nodes", v] => limits.nodes = v.parse().ok(),
+            ["movetime", v] => limits.movetime = v.parse().ok(),
+            ["threads", v] => threads = v.parse().unwrap_or(DEFAULT_THREADS),
+            ["hash", v] => hash = v.parse().unwrap_or(DEFAULT_HASH),
+            _ => eprintln!("Ignoring unknown argument: '{}'", pair.join(" ")),
+        }
+    }
+
+    if limits.depth.is_none() && limits.nodes.is_none() && limits.movetime.is_none() {
+        limits.movetime = Some(DEFAULT_MOVETIME);
+    }
+
+    let entries = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match parse_entry(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("Skipping line {}: {e}", index + 1);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let shared = Arc::new(SharedContext::default());
+    shared.tt.resize(threads, hash);
+
+    let mut pool = ThreadPool::new(shared.clone());
+    pool.set_count(threads);
+
+    let mut solved = 0;
+    let time = Instant::now();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let board = Board::from_fen(&entry.fen).unwrap();
+
+        pool.clear();
+        shared.tt.clear(pool.len());
+        pool.main_thread().writer = Box::new(BufferWriter::default());
+
+        let time_manager = TimeManager::new(limits.clone(), board.fullmove_number(), 0);
+        pool.execute_searches(time_manager, Report::Full, 1, &[], &board, &shared);
+
+        let played = pool.main_thread().root_moves[0].mv;
+        let output = pool.main_thread().writer.take();
+
+        let san = |moves: &[Move]| moves.iter().map(|mv| mv.to_san(&board)).collect::<Vec<_>>().join(" ");
+        let mut expected = String::new();
+        if !entry.best.is_empty() {
+            expected.push_str(&format!("bm {}", san(&entry.best)));
+        }
+        if !entry.avoid.is_empty() {
+            expected.push_str(&format!(" am {}", san(&entry.avoid)));
+        }
+
+        if entry.is_solved_by(played) {
+            solved += 1;
+
+            let ms = solution_time(entry, &board, &output);
+            println!("{:>4} {:<16} pass {:>8.3}s  {}", index + 1, entry.id, ms as f64 / 1000.0, expected.trim());
+        } else {
+            println!(
+                "{:>4} {:<16} FAIL {:>9}  {} (played {})",
+                index + 1,
+                entry.id,
+                "",
+                expected.trim(),
+                played.to_san(&board)
+            );
+        }
+    }
+
+    let total = entries.len().max(1);
+    println!(
+        "Solved {solved}/{} ({:.1}%) in {:.3}s",
+        entries.len(),
+        100.0 * solved as f64 / total as f64,
+        time.elapsed().as_secs_f64()
+    );
+}
+
+/// Returns the reported search time (in ms) from which the principal move
+/// solved the position without changing to a wrong move again.
+fn solution_time(entry: &Entry, board: &Board, output: &str) -> u64 {
+    let mut since = None;
+    let mut last = 0;
+
+    for line in output.lines().filter(|line| line.contains(" multipv 1 ") && line.contains(" pv ")) {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+
+        let time = tokens.iter().position(|&t| t == "time").and_then(|i| tokens.get(i + 1)?.parse().ok());
+        let first = tokens.iter().position(|&t| t == "pv").and_then(|i| tokens.get(i + 1));
+
+        let (Some(time), Some(first)) = (time, first) else { continue };
+        last = time;
+
+        match board.find_uci_move(first) {
+            Some(mv) if entry.is_solved_by(mv) => since = since.or(Some(time)),
+            _ => since = None,
+        }
+    }
+
+    since.unwrap_or(last)
+}
+
+fn parse_entry(line: &str) -> Result<Entry, String> {
+    let mut fen = Vec::new();
+    let mut rest = line;
+    for _ in 0..4 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err("missing FEN fields".to_string());
+        }
+
+        fen.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let fen = fen.join(" ");
+    let board = Board::from_fen(&fen).map_err(|e| format!("invalid FEN: {e:?}"))?;
+
+    let mut entry = Entry { id: String::new(), fen, best: Vec::new(), avoid: Vec::new() };
+
+    for operation in split_operations(rest) {
+        let (opcode, operands) = operation.split_once(char::is_whitespace).unwrap_or((operation, ""));
+
+        match opcode {
+            "id" => entry.id = operands.trim().trim_matches('"').to_string(),
+            "bm" | "am" => {
+                for san in operands.split_whitespace() {
+                    let mv = board.parse_san(san).map_err(|e| format!("invalid move '{san}': {e:?}"))?;
+
+                    if opcode == "bm" {
+                        entry.best.push(mv);
+                    } else {
+                        entry.avoid.push(mv);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if entry.best.is_empty() && entry.avoid.is_empty() {
+        return Err("no bm or am operation".to_string());
+    }
+
+    Ok(entry)
+}
+
+/// Splits the operations of an EPD record on the `;` terminators, keeping
+/// quoted operands such as `c0 "a; b";` in one piece.
+fn split_operations(text: &str) -> Vec<&str> {
+    let mut operations = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                operations.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    operations.push(&text[start..]);
+    operations.into_iter().map(str::trim).filter(|op| !op.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_001: &str = "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - -";
+
+    fn board(entry: &Entry) -> Board {
+        Board::from_fen(&entry.fen).unwrap()
+    }
+
+    #[test]
+    fn parses_best_moves_and_id() {
+        let entry = parse_entry(&format!("{WAC_001} bm Qg6; id \"WAC.001\";")).unwrap();
+        let board = board(&entry);
+
+        assert_eq!(entry.id, "WAC.001");
+        assert_eq!(entry.best, vec![board.find_uci_move("g3g6").unwrap()]);
+        assert!(entry.avoid.is_empty());
+    }
+
+    #[test]
+    fn parses_several_best_and_avoid_moves() {
+        let entry = parse_entry(&format!("{WAC_001} bm Qg6 Nxf7+; am Qg5; id \"x\";")).unwrap();
+        let board = board(&entry);
+
+        assert_eq!(entry.best, vec![board.find_uci_move("g3g6").unwrap(), board.find_uci_move("e5f7").unwrap()]);
+        assert_eq!(entry.avoid, vec![board.find_uci_move("g3g5").unwrap()]);
+    }
+
+    #[test]
+    fn quoted_operands_may_contain_semicolons() {
+        let entry = parse_entry(&format!("{WAC_001} c0 \"mate; or not\"; id \"a;  b\"; am Qg5;")).unwrap();
+        let board = board(&entry);
+
+        assert_eq!(entry.id, "a;  b");
+        assert_eq!(entry.avoid, vec![board.find_uci_move("g3g5").unwrap()]);
+    }
+
+    #[test]
+    fn rejects_incomplete_records() {
+        assert!(parse_entry("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w -").is_err());
+        assert!(parse_entry(&format!("{WAC_001} id \"WAC.001\";")).is_err());
+        assert!(parse_entry(WAC_001).is_err());
+        assert!(parse_entry(&format!("{WAC_001} bm Qh8;")).is_err());
+    }
+
+    #[test]
+    fn solution_time_counts_from_the_last_switch_to_a_solution() {
+        let entry = parse_entry(&format!("{WAC_001} bm Qg6;")).unwrap();
+        let board = board(&entry);
+
+        let output = [
+            "info depth 1 seldepth 2 multipv 1 score cp 30 nodes 40 time 1 pv g3g5",
+            "info depth 2 seldepth 3 multipv 1 score cp 200 nodes 90 time 5 pv g3g6 f7g6",
+            "info depth 3 seldepth 4 multipv 1 score cp 40 nodes 300 time 12 pv g3g5",
+            "info depth 4 seldepth 5 multipv 1 score mate 2 nodes 900 time 20 pv g3g6 f7g6",
+            "info depth 5 seldepth 6 multipv 1 score mate 2 nodes 1500 time 31 pv g3g6 f7g6",
+            "info depth 5 seldepth 6 multipv 2 score cp 10 nodes 1500 time 31 pv g3g5",
+        ]
+        .join("\n");
+
+        assert_eq!(solution_time(&entry, &board, &output), 20);
+    }
+
+    #[test]
+    fn unsolved_positions_report_the_last_time() {
+        let entry = parse_entry(&format!("{WAC_001} bm Qg6;")).unwrap();
+        let board = board(&entry);
+
+        let output = "info depth 1 seldepth 2 multipv 1 score cp 30 nodes 40 time 1 pv g3g6\n\
+                      info depth 2 seldepth 3 multipv 1 score cp 20 nodes 90 time 7 pv g3g5";
+
+        assert_eq!(solution_time(&entry, &board, output), 7);
+    }
+}
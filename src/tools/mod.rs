@@ -1,9 +1,17 @@
 mod bench;
+mod datagen;
+mod epd;
+mod network;
 mod perft;
 mod speedtest;
+mod tbprobe;
 
 pub use bench::bench;
+pub use datagen::datagen;
+pub use epd::epd;
+pub use network::{convertnet, netinfo};
 pub use perft::is_legal_perft;
 pub use perft::perft;
 pub use perft::simple_perft;
 pub use speedtest::speedtest;
+pub use tbprobe::tbprobe;
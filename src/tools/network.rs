@@ -0,0 +1,18 @@
+//! Commands for inspecting and converting network files.
+
+use crate::nnue;
+
+/// Prints the header of the active network.
+pub fn netinfo() {
+    for line in nnue::network_info() {
+        println!("{line}");
+    }
+}
+
+/// Converts a headerless network file into the versioned container format.
+pub fn convertnet(input: &str, output: &str) {
+    match nnue::convert(input, output) {
+        Ok(header) => println!("Converted '{input}' to '{output}' (content hash {:016x})", header.content_hash),
+        Err(e) => eprintln!("Failed to convert '{input}': {e}"),
+    }
+}
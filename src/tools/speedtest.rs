@@ -92,8 +92,8 @@ pub fn speedtest(args: &[&str]) {
         eprint!("Warmup {} of {}\r", index + 1, WARMUP_POSITIONS_COUNT);
 
         let board = Board::from_fen(position).unwrap();
-        let time_manager = TimeManager::new(Limits::Time(ms_per_position), 0, 0);
-        pool.execute_searches(time_manager, Report::None, 1, &board, &shared);
+        let time_manager = TimeManager::new(Limits { movetime: Some(ms_per_position), ..Default::default() }, 0, 0);
+        pool.execute_searches(time_manager, Report::None, 1, &[], &board, &shared);
     }
     eprintln!();
 
@@ -104,8 +104,8 @@ pub fn speedtest(args: &[&str]) {
         eprint!("Position {} of {}\r", index + 1, POSITIONS.len());
 
         let board = Board::from_fen(position).unwrap();
-        let time_manager = TimeManager::new(Limits::Time(ms_per_position), 0, 0);
-        pool.execute_searches(time_manager, Report::None, 1, &board, &shared);
+        let time_manager = TimeManager::new(Limits { movetime: Some(ms_per_position), ..Default::default() }, 0, 0);
+        pool.execute_searches(time_manager, Report::None, 1, &[], &board, &shared);
 
         nodes += shared.nodes.aggregate();
     }
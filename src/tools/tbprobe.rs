@@ -0,0 +1,91 @@
+//! Prints what the loaded tablebases say about a position and each of its legal moves,
+//! without running a search. Useful for debugging endgame behaviour.
+//!
+//! The WDL and DTZ of the moves take the fifty-move counter of the position into account,
+//! while the ranks follow the `Syzygy50MoveRule` option, as they would at the root of a search.
+//! The table files are not listed, as the probers do not report which ones they read.
+
+use std::sync::atomic::Ordering;
+
+use crate::{
+    board::{Board, NullBoardObserver},
+    tb::Wdl,
+    thread::SharedContext,
+    types::PieceType,
+};
+
+pub fn tbprobe(board: &Board, shared: &SharedContext) {
+    if board.castling().raw() != 0 {
+        println!("Positions with castling rights are not in the tablebases");
+        return;
+    }
+
+    let Some(wdl) = shared.tablebases.wdl(board) else {
+        println!("Position not found in the tablebases");
+        return;
+    };
+
+    let rule50 = shared.syzygy_50_move_rule.load(Ordering::Relaxed);
+    let ranks = shared.tablebases.rank_root(board, rule50).map(|(_, ranked)| ranked).unwrap_or_default();
+    let probe = shared.tablebases.dtz(board);
+
+    let (wdl, dtz) = match &probe {
+        Some((wdl, dtz, _)) => (*wdl, dtz.to_string()),
+        None => (wdl, "-".to_string()),
+    };
+
+    println!("{:<8} {}", "WDL", describe(wdl));
+    println!("{:<8} {dtz}", "DTZ");
+
+    if !board.has_legal_moves() {
+        return;
+    }
+
+    println!("{}", "-".repeat(60));
+    println!("{:>10} {:>14} {:>8} {:>10} {:>10}", "Move", "WDL", "DTZ", "Rank", "Zeroing");
+    println!("{}", "-".repeat(60));
+
+    for (index, entry) in board.generate_all_moves().iter().enumerate() {
+        let mv = entry.mv;
+
+        let (wdl, dtz) = match probe.as_ref().and_then(|(.., moves)| moves.iter().find(|probed| probed.mv == mv)) {
+            Some(probed) => (Some(probed.wdl), probed.dtz.to_string()),
+            None => {
+                let mut child = board.clone();
+                child.make_move(mv, &mut NullBoardObserver);
+                (shared.tablebases.wdl(&child).map(Wdl::flip), "-".to_string())
+            }
+        };
+
+        let rank = match ranks.iter().find(|ranked| ranked.mv == mv) {
+            Some(ranked) => ranked.rank.to_string(),
+            None => "-".to_string(),
+        };
+
+        let zeroing = mv.is_capture() || board.moved_piece(mv).piece_type() == PieceType::Pawn;
+
+        println!(
+            "{:>3} {:>6} {:>14} {dtz:>8} {rank:>10} {:>10}",
+            index + 1,
+            mv.to_uci(board),
+            wdl.map_or("-", describe),
+            if zeroing { "yes" } else { "no" },
+        );
+    }
+
+    println!("{}", "-".repeat(60));
+
+    if matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss) {
+        println!("The position is a {}, drawn under the fifty-move rule", describe(wdl));
+    }
+}
+
+const fn describe(wdl: Wdl) -> &'static str {
+    match wdl {
+        Wdl::Win => "win",
+        Wdl::CursedWin => "cursed win",
+        Wdl::Draw => "draw",
+        Wdl::BlessedLoss => "blessed loss",
+        Wdl::Loss => "loss",
+    }
+}
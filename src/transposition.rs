@@ -1,9 +1,21 @@
-use std::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering},
+};
 
 use crate::types::{Move, Score, is_decisive, is_loss, is_valid, is_win};
 
 pub const DEFAULT_TT_SIZE: usize = 16;
 
+/// Size of the table holding the pending writes of a thread in deterministic mode, in megabytes.
+const PENDING_TT_SIZE: usize = 4;
+
+/// The number of pending writes a thread keeps for the merge in deterministic mode.
+const PENDING_WRITES: usize = 1 << 16;
+
 const MEGABYTE: usize = 1024 * 1024;
 const CLUSTER_SIZE: usize = std::mem::size_of::<Cluster>();
 
@@ -12,6 +24,9 @@ const ENTRIES_PER_CLUSTER: usize = 3;
 const AGE_CYCLE: u8 = 1 << 5;
 const AGE_MASK: u8 = AGE_CYCLE - 1;
 
+const HASH_FILE_MAGIC: [u8; 8] = *b"RKLSHASH";
+const HASH_FILE_VERSION: u32 = 1;
+
 const _: () = assert!(std::mem::size_of::<Cluster>() == 32);
 const _: () = assert!(std::mem::size_of::<InternalEntry>() == 8);
 
@@ -127,6 +142,88 @@ impl Cluster {
         let matches = zeros.wrapping_sub(bits) & !zeros & (bits << 15);
         (matches.trailing_zeros() / 16) as usize
     }
+
+    fn to_bytes(&self) -> [u8; CLUSTER_SIZE] {
+        let mut bytes = [0; CLUSTER_SIZE];
+
+        for (chunk, entry) in bytes.chunks_exact_mut(8).zip(&self.entries) {
+            chunk[0..2].copy_from_slice(&entry.mv.raw().to_le_bytes());
+            chunk[2..4].copy_from_slice(&entry.score.to_le_bytes());
+            chunk[4..6].copy_from_slice(&entry.raw_eval.to_le_bytes());
+            chunk[6] = entry.offset_depth;
+            chunk[7] = entry.flags.data;
+        }
+
+        bytes[24..32].copy_from_slice(&self.keys.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; CLUSTER_SIZE]) -> Self {
+        let entry = |chunk: &[u8]| InternalEntry {
+            mv: Move::from_raw(u16::from_le_bytes([chunk[0], chunk[1]])).unwrap_or(Move::NULL),
+            score: i16::from_le_bytes([chunk[2], chunk[3]]),
+            raw_eval: i16::from_le_bytes([chunk[4], chunk[5]]),
+            offset_depth: chunk[6],
+            flags: Flags { data: chunk[7] },
+        };
+
+        Self {
+            entries: [entry(&bytes[0..8]), entry(&bytes[8..16]), entry(&bytes[16..24])],
+            keys: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+        }
+    }
+
+    /// Stores an entry carried over from another table, evicting the least valuable
+    /// entry if needed. Returns `false` if the entry was dropped because everything
+    /// in the cluster is worth more.
+    fn migrate(&mut self, entry: &InternalEntry, key: u16, tt_age: u8) -> bool {
+        let quality = |entry: &InternalEntry| match entry.flags.bound() {
+            Bound::None => i32::MIN,
+            _ => entry.depth() - 4 * entry.relative_age(tt_age),
+        };
+
+        let index = match self.lookup_key(key) {
+            index if index < self.entries.len() && self.entries[index].flags.bound() != Bound::None => index,
+            _ => (0..self.entries.len()).min_by_key(|&index| quality(&self.entries[index])).unwrap(),
+        };
+
+        if quality(&self.entries[index]) >= quality(entry) {
+            return false;
+        }
+
+        self.entries[index] = entry.clone();
+        self.set_key(index, key);
+        true
+    }
+}
+
+#[derive(Debug)]
+pub enum HashFileError {
+    Io(io::Error),
+    InvalidHeader,
+    UnsupportedVersion(u32),
+    EngineMismatch(String),
+    Truncated,
+}
+
+impl From<io::Error> for HashFileError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl fmt::Display for HashFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::InvalidHeader => write!(f, "not a hash file"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported hash file version {version}"),
+            Self::EngineMismatch(version) => {
+                write!(f, "saved by engine version {version}, expected {}", env!("ENGINE_VERSION"))
+            }
+            Self::Truncated => write!(f, "the file is truncated"),
+        }
+    }
 }
 
 /// The transposition table is used to cache previously performed search results.
@@ -145,15 +242,44 @@ impl TranspositionTable {
         self.age.store(0, Ordering::Relaxed);
     }
 
-    /// Resizes the transposition table to the specified size in megabytes. This will clear all entries.
-    pub fn resize(&self, threads: usize, megabytes: usize) {
-        unsafe { deallocate(self.ptr(), self.len()) };
+    /// Resizes the transposition table to the specified size in megabytes. Entries are moved into
+    /// the new table; when shrinking, the deepest and most recent ones are kept.
+    ///
+    /// When growing by a factor of `k`, only about one entry in `k` can be found again, see
+    /// `approximate_hash`. The others are moved all the same, as there is no telling which ones
+    /// they are, and take up their slots until they age out and are replaced.
+    ///
+    /// If the memory is not available, progressively smaller sizes are tried. Returns the size
+    /// actually allocated, or `None` if nothing could be allocated and the previous table was kept.
+    /// Both tables are allocated during the migration.
+    pub fn resize(&self, threads: usize, megabytes: usize) -> Option<usize> {
+        let (old_ptr, old_len) = (self.ptr(), self.len());
+
+        let mut megabytes = megabytes.max(1);
+        let (new_ptr, new_len) = loop {
+            if let Some(allocation) = unsafe { allocate(threads, megabytes) } {
+                break allocation;
+            }
 
-        let (new_ptr, new_len) = unsafe { allocate(threads, megabytes) };
+            if megabytes == 1 {
+                return None;
+            }
+            megabytes /= 2;
+        };
+
+        unsafe {
+            parallel_migrate(threads, old_ptr, old_len, new_ptr, new_len, self.age());
+            deallocate(old_ptr, old_len);
+        }
 
         self.ptr.store(new_ptr, Ordering::Relaxed);
         self.len.store(new_len, Ordering::Relaxed);
-        self.age.store(0, Ordering::Relaxed);
+        Some(megabytes)
+    }
+
+    /// Returns the size of the table in megabytes.
+    pub fn megabytes(&self) -> usize {
+        self.len() * CLUSTER_SIZE / MEGABYTE
     }
 
     /// Returns the approximate load factor of the transposition table in permille (on a scale of `0` to `1000`).
@@ -171,6 +297,98 @@ impl TranspositionTable {
         count / ENTRIES_PER_CLUSTER
     }
 
+    /// Writes the table to `path`. The file starts with a header holding the format version,
+    /// the engine version, the number of clusters and the current age, followed by the clusters.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HashFileError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let version = env!("ENGINE_VERSION").as_bytes();
+
+        writer.write_all(&HASH_FILE_MAGIC)?;
+        writer.write_all(&HASH_FILE_VERSION.to_le_bytes())?;
+        writer.write_all(&(version.len() as u16).to_le_bytes())?;
+        writer.write_all(version)?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        writer.write_all(&[self.age()])?;
+
+        let clusters = unsafe { std::slice::from_raw_parts(self.ptr(), self.len()) };
+        for cluster in clusters {
+            writer.write_all(&cluster.to_bytes())?;
+        }
+
+        Ok(writer.flush()?)
+    }
+
+    /// Replaces the contents of the table with a table saved by [`save`](Self::save) and returns
+    /// the number of restored entries. If the saved table has a different size, the entries are
+    /// re-indexed into the current one. The table is left untouched if the file is rejected.
+    pub fn load(&self, threads: usize, path: impl AsRef<Path>) -> Result<usize, HashFileError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(|_| HashFileError::InvalidHeader)?;
+        if magic != HASH_FILE_MAGIC {
+            return Err(HashFileError::InvalidHeader);
+        }
+
+        let mut buffer = [0; 4];
+        reader.read_exact(&mut buffer)?;
+        let format = u32::from_le_bytes(buffer);
+        if format != HASH_FILE_VERSION {
+            return Err(HashFileError::UnsupportedVersion(format));
+        }
+
+        let mut buffer = [0; 2];
+        reader.read_exact(&mut buffer)?;
+        let mut version = vec![0; u16::from_le_bytes(buffer) as usize];
+        reader.read_exact(&mut version)?;
+        let version = String::from_utf8_lossy(&version).into_owned();
+        if version != env!("ENGINE_VERSION") {
+            return Err(HashFileError::EngineMismatch(version));
+        }
+
+        let mut buffer = [0; 9];
+        reader.read_exact(&mut buffer)?;
+        let len = u64::from_le_bytes(buffer[0..8].try_into().unwrap());
+        let age = buffer[8] & AGE_MASK;
+
+        let header_size = (8 + 4 + 2 + version.len() + 9) as u64;
+        let expected_size = len.checked_mul(CLUSTER_SIZE as u64).and_then(|body| body.checked_add(header_size));
+        if expected_size != Some(file_size) {
+            return Err(HashFileError::Truncated);
+        }
+
+        // The clusters are read in full before the table is cleared, so that a read that fails
+        // halfway leaves the table as it was
+        let len = usize::try_from(len).map_err(|_| HashFileError::Truncated)?;
+        let mut body = Vec::new();
+        body.try_reserve_exact(len * CLUSTER_SIZE).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        body.resize(len * CLUSTER_SIZE, 0);
+        reader.read_exact(&mut body)?;
+
+        self.clear(threads);
+        self.age.store(age, Ordering::Relaxed);
+
+        let clusters = unsafe { std::slice::from_raw_parts_mut(self.ptr(), self.len()) };
+        let mut restored = 0;
+
+        for (saved_index, bytes) in body.chunks_exact(CLUSTER_SIZE).enumerate() {
+            let saved = Cluster::from_bytes(bytes.try_into().unwrap());
+
+            for (slot, entry) in saved.entries.iter().enumerate() {
+                if entry.flags.bound() == Bound::None {
+                    continue;
+                }
+
+                let hash = approximate_hash(saved_index, len, saved.key(slot));
+                restored += clusters[index(hash, self.len())].migrate(entry, verification_key(hash), age) as usize;
+            }
+        }
+
+        Ok(restored)
+    }
+
     pub fn increment_age(&self) {
         self.age.store((self.age() + 1) & AGE_MASK, Ordering::Relaxed);
     }
@@ -281,6 +499,16 @@ impl TranspositionTable {
         let _ = hash;
     }
 
+    /// Allocates a table of the given size, or returns `None` if the memory is not available.
+    fn try_with_size(megabytes: usize) -> Option<Self> {
+        let (ptr, len) = unsafe { allocate(1, megabytes) }?;
+        Some(Self {
+            ptr: AtomicPtr::new(ptr),
+            len: AtomicUsize::new(len),
+            age: AtomicU8::new(0),
+        })
+    }
+
     fn age(&self) -> u8 {
         self.age.load(Ordering::Relaxed)
     }
@@ -294,12 +522,85 @@ impl TranspositionTable {
     }
 }
 
+/// The writes of one thread during an iteration of a deterministic search. The thread reads them
+/// back before the shared table, which only changes between iterations, when the threads merge
+/// their pending writes into it in order of their ids.
+///
+/// The writes kept for the merge have a fixed number of slots, indexed by hash like the table.
+/// A write only takes the slot of another position if it is at least as deep.
+pub struct PendingWrites {
+    table: TranspositionTable,
+    writes: Box<[Option<PendingWrite>]>,
+}
+
+#[derive(Copy, Clone)]
+struct PendingWrite {
+    hash: u64,
+    depth: i32,
+    raw_eval: i32,
+    score: i32,
+    bound: Bound,
+    mv: Move,
+    ply: isize,
+    tt_pv: bool,
+    force: bool,
+}
+
+impl PendingWrites {
+    /// Allocates the table of the pending writes, or returns `None` if the memory is not available.
+    pub fn try_new() -> Option<Self> {
+        let mut writes = Vec::new();
+        writes.try_reserve_exact(PENDING_WRITES).ok()?;
+        writes.resize(PENDING_WRITES, None);
+
+        Some(Self {
+            table: TranspositionTable::try_with_size(PENDING_TT_SIZE)?,
+            writes: writes.into_boxed_slice(),
+        })
+    }
+
+    pub fn read(&self, shared: &TranspositionTable, hash: u64, halfmove_clock: u8, ply: isize) -> Option<Entry> {
+        self.table.read(hash, halfmove_clock, ply).or_else(|| shared.read(hash, halfmove_clock, ply))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn write(
+        &mut self, hash: u64, depth: i32, raw_eval: i32, score: i32, bound: Bound, mv: Move, ply: isize, tt_pv: bool,
+        force: bool,
+    ) {
+        self.table.write(hash, depth, raw_eval, score, bound, mv, ply, tt_pv, force);
+
+        let slot = &mut self.writes[index(hash, PENDING_WRITES)];
+        if slot.is_none_or(|pending| pending.hash == hash || depth >= pending.depth) {
+            *slot = Some(PendingWrite { hash, depth, raw_eval, score, bound, mv, ply, tt_pv, force });
+        }
+    }
+
+    /// Writes the pending writes into `shared` in the order of their slots, and forgets them.
+    pub fn merge_into(&mut self, shared: &TranspositionTable) {
+        for write in self.writes.iter_mut().filter_map(Option::take) {
+            let PendingWrite { hash, depth, raw_eval, score, bound, mv, ply, tt_pv, force } = write;
+            shared.write(hash, depth, raw_eval, score, bound, mv, ply, tt_pv, force);
+        }
+        self.table.clear(1);
+    }
+}
+
 const fn index(hash: u64, len: usize) -> usize {
     // Fast hash table index calculation
     // For details, see: https://lemire.me/blog/2016/06/27/a-fast-alternative-to-the-modulo-reduction
     (((hash as u128) * (len as u128)) >> 64) as usize
 }
 
+/// Reconstructs a hash that maps to cluster `index` of a table with `len` clusters and has
+/// the given verification key. The bits in between are lost, so when entries move into a
+/// larger table, each goes to one of the clusters its hash could map to and is only found
+/// again if that guess was right. The others are eventually replaced.
+const fn approximate_hash(index: usize, len: usize, key: u16) -> u64 {
+    let midpoint = (((index as u128) << 64) + (1 << 63)) / len as u128;
+    (midpoint as u64 & !0xFFFF) | key as u64
+}
+
 /// Returns the verification key of the hash (bottom 16 bits).
 const fn verification_key(hash: u64) -> u16 {
     hash as u16
@@ -346,12 +647,7 @@ const fn score_from_tt(score: i32, ply: isize, halfmove_clock: u8) -> i32 {
 
 impl Default for TranspositionTable {
     fn default() -> Self {
-        let (ptr, len) = unsafe { allocate(1, DEFAULT_TT_SIZE) };
-        Self {
-            ptr: AtomicPtr::new(ptr),
-            len: AtomicUsize::new(len),
-            age: AtomicU8::new(0),
-        }
+        Self::try_with_size(DEFAULT_TT_SIZE).expect("Failed to allocate the transposition table")
     }
 }
 
@@ -361,28 +657,36 @@ impl Drop for TranspositionTable {
     }
 }
 
-unsafe fn allocate(threads: usize, size_mb: usize) -> (*mut Cluster, usize) {
+/// Allocates a zeroed table of the given size, or returns `None` if the memory is not available.
+unsafe fn allocate(threads: usize, size_mb: usize) -> Option<(*mut Cluster, usize)> {
     #[cfg(target_os = "linux")]
-    use libc::{MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, madvise, mmap};
+    use libc::{MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, PROT_READ, PROT_WRITE, madvise, mmap};
 
-    let size = size_mb * MEGABYTE;
+    let size = size_mb.checked_mul(MEGABYTE)?;
     let len = size / CLUSTER_SIZE;
 
     #[cfg(target_os = "linux")]
     let ptr = {
         let ptr = mmap(std::ptr::null_mut(), size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if ptr == MAP_FAILED {
+            return None;
+        }
         madvise(ptr, size, MADV_HUGEPAGE);
         ptr.cast()
     };
 
     #[cfg(not(target_os = "linux"))]
     let ptr = {
-        let layout = std::alloc::Layout::from_size_align(size, std::mem::align_of::<Cluster>()).unwrap();
-        std::alloc::alloc_zeroed(layout).cast()
+        let layout = std::alloc::Layout::from_size_align(size, std::mem::align_of::<Cluster>()).ok()?;
+        let ptr = std::alloc::alloc_zeroed(layout);
+        if ptr.is_null() {
+            return None;
+        }
+        ptr.cast()
     };
 
     unsafe { parallel_clear(threads, ptr, len) };
-    (ptr, len)
+    Some((ptr, len))
 }
 
 unsafe fn deallocate(ptr: *mut Cluster, len: usize) {
@@ -398,6 +702,54 @@ unsafe fn deallocate(ptr: *mut Cluster, len: usize) {
     }
 }
 
+/// Moves the entries of `src` into the empty table `dst`. The destination is split between
+/// threads, and each thread scans the part of the source that maps into its share, so that
+/// every destination cluster is only written by a single thread.
+unsafe fn parallel_migrate(
+    threads: usize, src: *const Cluster, src_len: usize, dst: *mut Cluster, dst_len: usize, tt_age: u8,
+) {
+    let src = std::slice::from_raw_parts(src, src_len);
+    let dst = std::slice::from_raw_parts_mut(dst, dst_len);
+
+    let scale = |index: usize, from: usize, to: usize| (index as u128 * to as u128 / from as u128) as usize;
+
+    let migrate = |start: usize, chunk: &mut [Cluster]| {
+        let end = start + chunk.len();
+        let first = scale(start, dst_len, src_len).saturating_sub(1);
+        let last = (scale(end, dst_len, src_len) + 2).min(src_len);
+
+        for (offset, cluster) in src[first..last].iter().enumerate() {
+            for (slot, entry) in cluster.entries.iter().enumerate() {
+                if entry.flags.bound() == Bound::None {
+                    continue;
+                }
+
+                let hash = approximate_hash(first + offset, src_len, cluster.key(slot));
+                let target = index(hash, dst_len);
+                if (start..end).contains(&target) {
+                    chunk[target - start].migrate(entry, verification_key(hash), tt_age);
+                }
+            }
+        }
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::scope(|scope| {
+        let migrate = &migrate;
+
+        let chunk_size = dst_len.div_ceil(threads);
+        for (index, chunk) in dst.chunks_mut(chunk_size).enumerate() {
+            scope.spawn(move || migrate(index * chunk_size, chunk));
+        }
+    });
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = threads;
+        migrate(0, dst);
+    }
+}
+
 unsafe fn parallel_clear<T: std::marker::Send>(threads: usize, ptr: *mut T, len: usize) {
     #[cfg(not(target_arch = "wasm32"))]
     std::thread::scope(|scope| {
@@ -415,3 +767,74 @@ unsafe fn parallel_clear<T: std::marker::Send>(threads: usize, ptr: *mut T, len:
         ptr.write_bytes(0, len);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::SplitMix64;
+
+    fn occupied(tt: &TranspositionTable) -> usize {
+        let clusters = unsafe { std::slice::from_raw_parts(tt.ptr(), tt.len()) };
+        clusters.iter().flat_map(|cluster| &cluster.entries).filter(|entry| entry.flags.bound() != Bound::None).count()
+    }
+
+    fn fill(tt: &TranspositionTable, count: usize) -> Vec<u64> {
+        let mut rng = SplitMix64::new(7);
+        let hashes = (0..count).map(|_| rng.next()).collect::<Vec<_>>();
+        for &hash in &hashes {
+            tt.write(hash, 5, 0, 0, Bound::Exact, Move::NULL, 0, false, false);
+        }
+        hashes
+    }
+
+    #[test]
+    fn growing_places_each_entry_once() {
+        let tt = TranspositionTable::default();
+        tt.resize(1, 1);
+        let hashes = fill(&tt, 10_000);
+
+        let before = occupied(&tt);
+        tt.resize(1, 8);
+        assert_eq!(occupied(&tt), before);
+
+        // Growing eightfold, about one entry in eight lands in the cluster of its hash
+        let found = hashes.iter().filter(|&&hash| tt.read(hash, 0, 0).is_some()).count();
+        assert!((1000..=1500).contains(&found), "{found} entries found");
+    }
+
+    #[test]
+    fn rejected_hash_files_leave_the_table_intact() {
+        let tt = TranspositionTable::default();
+        tt.resize(1, 1);
+        let hashes = fill(&tt, 1_000);
+
+        let path = std::env::temp_dir().join(format!("reckless-hash-{}.bin", std::process::id()));
+        tt.save(&path).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+
+        // A cluster count that overflows the expected file size
+        let mut crafted = saved.clone();
+        let len_offset = saved.len() - tt.len() * CLUSTER_SIZE - 9;
+        crafted[len_offset..len_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &crafted).unwrap();
+        assert!(matches!(tt.load(1, &path), Err(HashFileError::Truncated)));
+
+        std::fs::write(&path, &saved[..saved.len() - 1]).unwrap();
+        assert!(matches!(tt.load(1, &path), Err(HashFileError::Truncated)));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(hashes.iter().all(|&hash| tt.read(hash, 0, 0).is_some()));
+    }
+
+    #[test]
+    fn shrinking_keeps_entries_findable() {
+        let tt = TranspositionTable::default();
+        tt.resize(1, 4);
+        let hashes = fill(&tt, 10_000);
+
+        tt.resize(1, 1);
+        let found = hashes.iter().filter(|&&hash| tt.read(hash, 0, 0).is_some()).count();
+        assert!(found > 0);
+        assert_eq!(found, occupied(&tt));
+    }
+}
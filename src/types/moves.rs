@@ -1,7 +1,7 @@
 use std::mem;
 
-use super::{PieceType, Square};
-use crate::board::Board;
+use super::{File, PieceType, Square};
+use crate::board::{Board, NullBoardObserver};
 
 /// Represents a chess move containing the from and to squares, as well as flags for special moves.
 /// The information encoded as a 16-bit integer, 6 bits for the from/to square and 4 bits for the flags.
@@ -41,6 +41,18 @@ impl Move {
         Self(from as u16 | ((to as u16) << 6) | ((kind as u16) << 12))
     }
 
+    /// Reconstructs a move from its 16-bit encoding, or returns `None` if the move kind is invalid.
+    pub const fn from_raw(raw: u16) -> Option<Self> {
+        match raw >> 12 {
+            0b0011 | 0b0110 | 0b0111 => None,
+            _ => Some(Self(raw)),
+        }
+    }
+
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
+
     pub const fn from(self) -> Square {
         Square::new((self.0 & 0b0011_1111) as u8)
     }
@@ -151,6 +163,84 @@ impl Move {
 
         output
     }
+
+    /// Formats the move in [Standard Algebraic Notation][san], including the disambiguation
+    /// and the check or mate suffix. Castling is written as `O-O`/`O-O-O` in both standard
+    /// chess and Chess960.
+    ///
+    /// [san]: https://en.wikipedia.org/wiki/Algebraic_notation_(chess)
+    pub fn to_san(self, board: &Board) -> String {
+        let mut output = String::new();
+
+        if self.is_castling() {
+            output.push_str(if self.to().file() == File::G { "O-O" } else { "O-O-O" });
+        } else {
+            let piece_type = board.moved_piece(self).piece_type();
+
+            if piece_type == PieceType::Pawn {
+                if self.is_capture() {
+                    output.push((b'a' + self.from().file() as u8) as char);
+                }
+            } else {
+                output.push(piece_letter(piece_type));
+
+                let rivals = board
+                    .generate_all_moves()
+                    .iter()
+                    .map(|entry| entry.mv)
+                    .filter(|&mv| {
+                        mv != self
+                            && mv.to() == self.to()
+                            && !mv.is_castling()
+                            && board.moved_piece(mv).piece_type() == piece_type
+                    })
+                    .collect::<Vec<_>>();
+
+                if !rivals.is_empty() {
+                    let same_file = rivals.iter().any(|mv| mv.from().file() == self.from().file());
+                    let same_rank = rivals.iter().any(|mv| mv.from().rank() == self.from().rank());
+
+                    if !same_file || same_rank {
+                        output.push((b'a' + self.from().file() as u8) as char);
+                    }
+                    if same_file {
+                        output.push((b'1' + self.from().rank() as u8) as char);
+                    }
+                }
+            }
+
+            if self.is_capture() {
+                output.push('x');
+            }
+
+            output.push_str(&self.to().to_string());
+
+            if self.is_promotion() {
+                output.push('=');
+                output.push(piece_letter(self.promo_piece_type()));
+            }
+        }
+
+        let mut after = board.clone();
+        after.make_move(self, &mut NullBoardObserver);
+
+        if after.in_check() {
+            output.push(if after.has_legal_moves() { '+' } else { '#' });
+        }
+
+        output
+    }
+}
+
+const fn piece_letter(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::Knight => 'N',
+        PieceType::Bishop => 'B',
+        PieceType::Rook => 'R',
+        PieceType::Queen => 'Q',
+        PieceType::King => 'K',
+        _ => 'P',
+    }
 }
 
 impl Default for Move {
@@ -17,8 +17,11 @@ impl Score {
     pub const TB_WIN_IN_MAX: i32 = Self::TB_WIN - MAX_PLY as i32;
 }
 
+/// The draw score from the side to move's perspective: a small jitter around the contempt.
+/// The jitter is left out in deterministic mode.
 pub fn draw(td: &ThreadData) -> i32 {
-    (td.nodes() % 5) as i32 - 2
+    let jitter = if td.deterministic { 0 } else { (td.nodes() % 5) as i32 - 2 };
+    jitter + td.contempt[td.board.side_to_move()]
 }
 
 pub const fn mated_in(ply: isize) -> i32 {
@@ -29,12 +32,10 @@ pub const fn mate_in(ply: isize) -> i32 {
     Score::MATE - ply as i32
 }
 
-#[cfg(feature = "syzygy")]
 pub const fn tb_loss_in(ply: isize) -> i32 {
     -Score::TB_WIN + ply as i32
 }
 
-#[cfg(feature = "syzygy")]
 pub const fn tb_win_in(ply: isize) -> i32 {
     Score::TB_WIN - ply as i32
 }
@@ -55,16 +56,115 @@ pub const fn is_valid(score: i32) -> bool {
     score != Score::NONE
 }
 
-pub fn normalize_to_cp(score: i32, board: &Board) -> i32 {
+/// Win, draw and loss probabilities in permille from the side to move's perspective.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Wdl {
+    pub win: i32,
+    pub draw: i32,
+    pub loss: i32,
+}
+
+impl Wdl {
+    pub const fn flip(self) -> Self {
+        Self { win: self.loss, draw: self.draw, loss: self.win }
+    }
+}
+
+fn material(board: &Board) -> i32 {
     let material = board.pieces(PieceType::Pawn).popcount()
         + 3 * board.pieces(PieceType::Knight).popcount()
         + 3 * board.pieces(PieceType::Bishop).popcount()
         + 5 * board.pieces(PieceType::Rook).popcount()
         + 9 * board.pieces(PieceType::Queen).popcount();
 
-    let v = material.clamp(16, 78) as f64 / 58.0;
+    material as i32
+}
+
+/// The spread of the win rate model relative to the score of a 50% win rate. It is the ratio of
+/// `b` to `a` in the model Stockfish fitted with the [WDL model](https://github.com/official-stockfish/WDL_model)
+/// scripts, at its reference material count of 58: `75.44 / 371.66`.
+const SPREAD: f64 = 0.203;
+
+/// Parameters of the logistic win rate model for the given material count.
+/// `a` is the internal score at which the side to move wins half of its games,
+/// `b` controls how quickly the win rate grows around that point.
+///
+/// `a` is the normalization the engine uses to report centipawns, and `b` is derived from it,
+/// so that the win, draw and loss rates depend on the reported centipawns alone: 100 cp wins
+/// half of the games at any material. How quickly they change around that point comes from
+/// Stockfish's fit rather than from games of this network.
+fn win_rate_params(board: &Board) -> (f64, f64) {
+    let v = material(board).clamp(16, 78) as f64 / 58.0;
+
+    let a = -285.1 * v.powi(3) + 642.5 * v.powi(2) - 455.5 * v + 464.8;
+    let b = SPREAD * a;
+
+    (a, b)
+}
+
+fn win_rate(score: f64, a: f64, b: f64) -> f64 {
+    1000.0 / (1.0 + ((a - score) / b).exp())
+}
+
+pub fn win_rate_model(score: i32, board: &Board) -> Wdl {
+    if is_win(score) {
+        return Wdl { win: 1000, draw: 0, loss: 0 };
+    }
+
+    if is_loss(score) {
+        return Wdl { win: 0, draw: 0, loss: 1000 };
+    }
+
+    let (a, b) = win_rate_params(board);
+    let score = score.clamp(-4000, 4000) as f64;
+
+    let win = win_rate(score, a, b).round() as i32;
+    let loss = win_rate(-score, a, b).round() as i32;
+
+    Wdl { win, draw: 1000 - win - loss, loss }
+}
+
+pub fn normalize_to_cp(score: i32, board: &Board) -> i32 {
+    let (a, _) = win_rate_params(board);
+    (100.0 * score as f64 / a).round() as i32
+}
+
+/// The inverse of `normalize_to_cp`.
+pub fn cp_to_score(cp: i32, board: &Board) -> i32 {
+    let (a, _) = win_rate_params(board);
+    (cp as f64 * a / 100.0).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_win_rate_model() {
+        let board = Board::starting_position();
+
+        let even = win_rate_model(0, &board);
+        assert_eq!(even.win, even.loss);
+        assert_eq!(even.win + even.draw + even.loss, 1000);
+
+        let ahead = win_rate_model(300, &board);
+        assert!(ahead.win > ahead.loss);
+        assert_eq!(ahead.flip(), win_rate_model(-300, &board));
+
+        let mate = win_rate_model(Score::MATE - 10, &board);
+        assert_eq!((mate.win, mate.draw, mate.loss), (1000, 0, 0));
+    }
+
+    #[test]
+    fn win_rates_follow_the_reported_centipawns() {
+        let boards = ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "8/5k2/8/3r4/8/2R5/4K3/8 w - - 0 1"]
+            .map(|fen| Board::from_fen(fen).unwrap());
 
-    let normalization = -285.1 * v.powi(3) + 642.5 * v.powi(2) - 455.5 * v + 464.8;
+        for board in &boards {
+            assert!(win_rate_model(cp_to_score(100, board), board).win.abs_diff(500) <= 1);
+        }
 
-    (100.0 * score as f64 / normalization).round() as i32
+        let [opening, endgame] = boards.each_ref().map(|board| win_rate_model(cp_to_score(50, board), board));
+        assert!(opening.win.abs_diff(endgame.win) <= 1 && opening.loss.abs_diff(endgame.loss) <= 1);
+    }
 }
@@ -1,16 +1,23 @@
 use std::collections::HashMap;
 use std::collections::VecDeque;
-use std::sync::Arc;
+use std::sync::{Arc, atomic::Ordering};
+
+mod options;
 
 use crate::{
     board::{Board, NullBoardObserver},
+    book::{Book, Selection},
+    contempt::{self, AnalysisContempt, Contempt},
+    mate::{MateResult, MateSearch},
+    retrograde,
     search::Report,
-    thread::{SharedContext, Status, ThreadData},
+    skill::{MAX_ELO, Skill},
+    thread::{RootMove, SharedContext, Status, ThreadData},
     threadpool::ThreadPool,
-    time::{Limits, TimeManager},
+    time::{Clock, Limits, TimeManager},
     tools,
-    transposition::DEFAULT_TT_SIZE,
-    types::{Color, MAX_MOVES, Move, Piece, Score, Square, is_decisive, is_loss, is_win},
+    types::{Color, MAX_PLY, Move, Piece, Score, Square, is_decisive, is_loss, is_win, win_rate_model},
+    uci::options::Value,
 };
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -24,6 +31,24 @@ struct Settings {
     multi_pv: usize,
     move_overhead: u64,
     report: Report,
+    skill: Skill,
+    invalid_moves: InvalidMoves,
+    own_book: bool,
+    book: Option<Book>,
+    book_depth: usize,
+    book_selection: Selection,
+    contempt: Contempt,
+    /// Directory the generated endgame tables are read from and written to.
+    endgame_table_cache: String,
+}
+
+/// What `position` does when the move list contains an illegal or unparseable move.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum InvalidMoves {
+    /// Keep the position from before the command.
+    Reject,
+    /// Keep the moves played up to the invalid one.
+    Truncate,
 }
 
 impl Default for Settings {
@@ -33,17 +58,29 @@ impl Default for Settings {
             multi_pv: 1,
             move_overhead: 100,
             report: Report::Full,
+            skill: Skill::default(),
+            invalid_moves: InvalidMoves::Reject,
+            own_book: false,
+            book: None,
+            book_depth: 20,
+            book_selection: Selection::Weighted,
+            contempt: Contempt::default(),
+            endgame_table_cache: String::new(),
         }
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn message_loop(mut buffer: VecDeque<String>) {
+pub fn message_loop(mut buffer: VecDeque<String>, net: Option<String>) {
     let shared = Arc::new(SharedContext::default());
     let mut settings = Settings::default();
     let mut threads = ThreadPool::new(shared.clone());
     let mut board = Board::starting_position();
 
+    if let Some(path) = net {
+        load_network(&mut threads, &shared, &path);
+    }
+
     let rx = spawn_listener(shared.clone());
 
     let mut mode = if buffer.is_empty() { Mode::Uci } else { Mode::Cli };
@@ -65,6 +102,8 @@ pub fn message_loop(mut buffer: VecDeque<String>) {
             ["uci"] => {
                 uci();
                 mode = Mode::Uci;
+                // GUIs parse the moves of the PV, so SAN is for people typing commands only
+                shared.print_san.store(false, Ordering::Relaxed);
             }
 
             ["isready"] => println!("readyok"),
@@ -84,17 +123,33 @@ pub fn message_loop(mut buffer: VecDeque<String>) {
             ["compiler"] => compiler(),
             ["eval"] => eval(threads.main_thread(), &board),
             ["d"] => println!("{board}"),
+            ["san", value @ ("on" | "off")] => shared.print_san.store(*value == "on", Ordering::Relaxed),
+            ["san", ..] => eprintln!("Usage: san <on|off>"),
             ["bench", args @ ..] => match mode {
                 Mode::Uci => tools::bench::<true>(args),
                 Mode::Cli => tools::bench::<false>(args),
             },
             ["speedtest", args @ ..] => tools::speedtest(args),
+            ["epd", args @ ..] => tools::epd(args),
+            ["datagen", args @ ..] => tools::datagen(args),
+            ["netinfo"] => tools::netinfo(),
+            ["savehash", path] => match shared.tt.save(path) {
+                Ok(()) => println!("info string Saved hash to '{path}'"),
+                Err(e) => println!("info string Failed to save hash to '{path}': {e}"),
+            },
+            ["loadhash", path] => match shared.tt.load(threads.len(), path) {
+                Ok(entries) => println!("info string Loaded {entries} hash entries from '{path}'"),
+                Err(e) => println!("info string Failed to load hash from '{path}': {e}"),
+            },
+            ["convertnet", input, output] => tools::convertnet(input, output),
+            ["convertnet", ..] => eprintln!("Usage: convertnet <input> <output>"),
             ["perft", depth] => tools::perft(depth.parse().unwrap(), &mut board),
             ["perft"] => eprintln!("Usage: perft <depth>"),
             ["simpleperft", depth] => tools::simple_perft(depth.parse().unwrap(), &mut board),
             ["simpleperft"] => eprintln!("Usage: simpleperft <depth>"),
             ["islegalperft", depth] => tools::is_legal_perft(depth.parse().unwrap(), &mut board),
             ["islegalperft"] => eprintln!("Usage: islegalperft <depth>"),
+            ["tbprobe"] => tools::tbprobe(&board, &shared),
 
             // Ignore empty lines
             [] => (),
@@ -127,7 +182,11 @@ fn spawn_listener(shared: Arc<SharedContext>) -> std::sync::mpsc::Receiver<Strin
 
             match message.trim_end() {
                 "isready" => println!("readyok"),
-                "stop" => shared.status.set(Status::STOPPED),
+                "stop" => {
+                    shared.pondering.store(false, Ordering::Release);
+                    shared.status.set(Status::STOPPED);
+                }
+                "ponderhit" => shared.pondering.store(false, Ordering::Release),
                 "quit" => {
                     shared.status.set(Status::STOPPED);
                     let _ = tx.send("quit".to_string());
@@ -138,6 +197,10 @@ fn spawn_listener(shared: Arc<SharedContext>) -> std::sync::mpsc::Receiver<Strin
                     // in the current state should be ignored silently.
                     // (https://backscattering.de/chess/uci/#unexpected)
                     if shared.status.get() != Status::RUNNING {
+                        // Set before the command is forwarded, so that a quick `ponderhit` cannot be lost
+                        if message.starts_with("go") && message.split_whitespace().any(|token| token == "ponder") {
+                            shared.pondering.store(true, Ordering::Release);
+                        }
                         let _ = tx.send(message);
                     }
                 }
@@ -151,16 +214,9 @@ fn spawn_listener(shared: Arc<SharedContext>) -> std::sync::mpsc::Receiver<Strin
 fn uci() {
     println!("id name Reckless {}", env!("ENGINE_VERSION"));
     println!("id author Arseniy Surkov, Shahin M. Shahin, and Styx");
-    println!("option name Hash type spin default {DEFAULT_TT_SIZE} min 1 max 262144");
-    println!("option name Threads type spin default 1 min 1 max {}", ThreadPool::available_threads());
-    println!("option name MoveOverhead type spin default 100 min 0 max 2000");
-    println!("option name Minimal type check default false");
-    println!("option name Clear Hash type button");
-    println!("option name UCI_Chess960 type check default false");
-    println!("option name MultiPV type spin default 1 min 1 max {MAX_MOVES}");
-
-    #[cfg(feature = "syzygy")]
-    println!("option name SyzygyPath type string default");
+    for option in options::registry() {
+        println!("{option}");
+    }
 
     #[cfg(feature = "spsa")]
     crate::parameters::print_options();
@@ -186,10 +242,52 @@ fn reset(threads: &mut ThreadPool, shared: &Arc<SharedContext>) {
 }
 
 fn go(threads: &mut ThreadPool, settings: &Settings, board: &Board, shared: &Arc<SharedContext>, tokens: &[&str]) {
-    let limits = parse_limits(board.side_to_move(), tokens);
+    let (tokens, searchmoves) = split_searchmoves(board, tokens);
+    let mut limits = parse_limits(board.side_to_move(), &tokens);
+    let mut multi_pv = settings.multi_pv;
+
+    if !limits.infinite
+        && !limits.ponder
+        && searchmoves.is_empty()
+        && let Some(mv) = book_move(settings, board)
+    {
+        println!("bestmove {}", mv.to_uci(board));
+        return;
+    }
+
+    if let Some(moves) = limits.mate
+        && !limits.infinite
+        && !limits.ponder
+    {
+        match solve_mate(board, settings, shared, &mut limits, moves, &searchmoves) {
+            Some((mv, Some(reply))) => {
+                let mut after = board.clone();
+                after.make_move(mv, &mut NullBoardObserver);
+                println!("bestmove {} ponder {}", mv.to_uci(board), reply.to_uci(&after));
+                return;
+            }
+            Some((mv, None)) => {
+                println!("bestmove {}", mv.to_uci(board));
+                return;
+            }
+            None => limits.mate = None,
+        }
+    }
+
+    let handicap = settings.skill.handicap();
+    if let Some(handicap) = handicap {
+        limits.nodes = Some(limits.nodes.map_or(handicap.nodes, |nodes| nodes.min(handicap.nodes)));
+        multi_pv = multi_pv.max(handicap.multi_pv);
+    }
+
+    let elo = settings.skill.target_elo().unwrap_or(MAX_ELO);
+    for (slot, score) in shared.contempt.iter().zip(settings.contempt.draw_scores(board, elo, limits.infinite)) {
+        slot.store(score, Ordering::Relaxed);
+    }
+
     let time_manager = TimeManager::new(limits, board.fullmove_number(), settings.move_overhead);
 
-    threads.execute_searches(time_manager, settings.report, settings.multi_pv, board, shared);
+    threads.execute_searches(time_manager, settings.report, multi_pv, &searchmoves, board, shared);
 
     if threads[0].root_moves.is_empty() {
         println!("bestmove (none)");
@@ -206,7 +304,7 @@ fn go(threads: &mut ThreadPool, settings: &Settings, board: &Board, shared: &Arc
 
     let mut best = 0;
 
-    if !matches!(threads[best].time_manager.limits(), Limits::Depth(_)) && threads[0].multi_pv == 1 {
+    if threads[best].time_manager.limits().depth.is_none() && threads[0].multi_pv == 1 {
         for current in 1..threads.len() {
             let is_better_candidate = || -> bool {
                 let best = &threads[best];
@@ -246,11 +344,100 @@ fn go(threads: &mut ThreadPool, settings: &Settings, board: &Board, shared: &Arc
         threads[best].print_uci_info(depth);
     }
 
-    println!("bestmove {}", threads[best].root_moves[0].mv.to_uci(board));
+    let choice = match handicap {
+        Some(handicap) => handicap.pick(&threads[best].root_moves[..threads[best].multi_pv], board.hash()),
+        None => 0,
+    };
+
+    let root_move = &threads[best].root_moves[choice];
+    let best_move = root_move.mv;
+
+    match ponder_move(root_move, board, shared) {
+        Some((ponder_move, after)) => {
+            println!("bestmove {} ponder {}", best_move.to_uci(board), ponder_move.to_uci(&after));
+        }
+        None => println!("bestmove {}", best_move.to_uci(board)),
+    }
+
     crate::misc::dbg_print();
 }
 
+/// Runs the mate solver and returns the first move of the shortest mate, with the reply to ponder
+/// on if the mate takes more than one move. If there is none, `limits` are adjusted for the
+/// regular search that picks the move instead.
+///
+/// The solver gets half of the time and nodes of the move, and the regular search whatever it
+/// left. Only after a `stop` does the regular search just complete depth 1. Without limits, the
+/// regular search is bounded by the mate length.
+fn solve_mate(
+    board: &Board, settings: &Settings, shared: &SharedContext, limits: &mut Limits, moves: u64, searchmoves: &[Move],
+) -> Option<(Move, Option<Move>)> {
+    shared.status.set(Status::RUNNING);
+
+    let budget = TimeManager::new(limits.clone(), board.fullmove_number(), settings.move_overhead).hard_bound();
+    let solver_limits = Limits {
+        nodes: limits.nodes.map(|nodes| nodes / 2),
+        movetime: limits.is_timed().then_some(budget.as_millis() as u64 / 2),
+        ..Default::default()
+    };
+
+    let start = std::time::Instant::now();
+    let mut solver = MateSearch::new(TimeManager::new(solver_limits, 0, 0), &shared.status);
+    let result = solver.solve(board, moves.min(MAX_PLY as u64 / 2) as u32, searchmoves);
+
+    let interrupted = shared.status.get() == Status::STOPPED;
+    shared.status.set(Status::STOPPED);
+
+    match result {
+        MateResult::Found { moves, pv } => {
+            println!("info string Mate in {moves} proven");
+            return pv.first().map(|&mv| (mv, pv.get(1).copied()));
+        }
+        MateResult::NoMate => println!("info string No mate in {moves}"),
+        MateResult::Stopped { moves: 0 } => println!("info string Mate search stopped"),
+        MateResult::Stopped { moves } => println!("info string Mate search stopped, no mate in {moves}"),
+    }
+
+    if matches!(result, MateResult::Stopped { .. }) && interrupted {
+        *limits = Limits { depth: Some(1), ..Default::default() };
+    } else if matches!(result, MateResult::Stopped { .. }) {
+        limits.deduct(start.elapsed().as_millis() as u64, solver.nodes());
+    } else if limits.depth.is_none() && limits.nodes.is_none() && !limits.is_timed() {
+        limits.depth = Some(2 * moves as i32);
+    }
+
+    None
+}
+
+/// Picks the expected reply to the chosen move: the second PV move, or a TT guess when the PV is
+/// too short. Returns the move together with the position it is played in.
+fn ponder_move(root_move: &RootMove, board: &Board, shared: &SharedContext) -> Option<(Move, Board)> {
+    let mut board = board.clone();
+    board.make_move(root_move.mv, &mut NullBoardObserver);
+
+    let candidate = match root_move.pv.line().first() {
+        Some(&mv) => mv,
+        None => shared.tt.read(board.hash(), board.fiftymove_clock(), 0)?.mv,
+    };
+
+    let is_legal = candidate.is_present() && board.generate_all_moves().iter().any(|entry| entry.mv == candidate);
+    is_legal.then_some((candidate, board))
+}
+
+/// Returns a move from the opening book if `OwnBook` is enabled and the game is still within `BookDepth`.
+fn book_move(settings: &Settings, board: &Board) -> Option<Move> {
+    let book = settings.book.as_ref().filter(|_| settings.own_book)?;
+    if board.fullmove_number() > settings.book_depth {
+        return None;
+    }
+
+    let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+    book.pick(board, settings.book_selection, seed)
+}
+
 fn position(board: &mut Board, settings: &Settings, mut tokens: &[&str]) {
+    let original = board.clone();
+
     while !tokens.is_empty() {
         match tokens {
             ["startpos", rest @ ..] => {
@@ -266,9 +453,31 @@ fn position(board: &mut Board, settings: &Settings, mut tokens: &[&str]) {
                 tokens = rest;
             }
             ["moves", rest @ ..] => {
-                for uci_move in rest {
-                    make_uci_move(board, uci_move);
+                let mut updated = board.clone();
+
+                for (index, uci_move) in rest.iter().enumerate() {
+                    if make_uci_move(&mut updated, uci_move) {
+                        continue;
+                    }
+
+                    let error = if is_uci_notation(uci_move) { "Illegal" } else { "Unparseable" };
+
+                    match settings.invalid_moves {
+                        InvalidMoves::Reject => {
+                            println!("info string {error} move '{uci_move}' at index {index}, position unchanged");
+                            *board = original;
+                            return;
+                        }
+                        InvalidMoves::Truncate => {
+                            println!(
+                                "info string {error} move '{uci_move}' at index {index}, stopping after {index} moves"
+                            );
+                            break;
+                        }
+                    }
                 }
+
+                *board = updated;
                 break;
             }
             _ => tokens = &tokens[1..],
@@ -276,56 +485,224 @@ fn position(board: &mut Board, settings: &Settings, mut tokens: &[&str]) {
     }
 }
 
-fn make_uci_move(board: &mut Board, uci_move: &str) {
-    let moves = board.generate_all_moves();
-    if let Some(mv) = moves.iter().map(|entry| entry.mv).find(|mv| mv.to_uci(board) == uci_move) {
-        board.make_move(mv, &mut NullBoardObserver);
+/// Checks the shape of a UCI move (`e2e4`, `e7e8q`) without looking at the position.
+fn is_uci_notation(uci_move: &str) -> bool {
+    let bytes = uci_move.as_bytes();
+    let is_square = |file: u8, rank: u8| (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank);
+
+    match bytes {
+        [f1, r1, f2, r2, rest @ ..] => {
+            is_square(*f1, *r1) && is_square(*f2, *r2) && matches!(rest, [] | [b'q' | b'r' | b'b' | b'n'])
+        }
+        _ => false,
+    }
+}
+
+/// Plays `uci_move` if it is legal in the current position, returning whether it was played.
+fn make_uci_move(board: &mut Board, uci_move: &str) -> bool {
+    match board.find_uci_move(uci_move) {
+        Some(mv) => {
+            board.make_move(mv, &mut NullBoardObserver);
+            true
+        }
+        None => false,
     }
 }
 
 fn set_option(threads: &mut ThreadPool, settings: &mut Settings, shared: &Arc<SharedContext>, tokens: &[&str]) {
-    match tokens {
-        ["name", "Minimal", "value", v] => match *v {
-            "true" => settings.report = Report::Minimal,
-            "false" => settings.report = Report::Full,
-            _ => eprintln!("Invalid value: '{v}'"),
-        },
-        ["name", "Clear", "Hash"] => {
+    let registry = options::registry();
+
+    let (option, value) = match options::parse(&registry, tokens) {
+        Ok(parsed) => parsed,
+        #[cfg(feature = "spsa")]
+        Err(_) if matches!(tokens, ["name", _, "value", _]) => {
+            crate::parameters::set_parameter(tokens[1], tokens[3]);
+            println!("info string set {} to {}", tokens[1], tokens[3]);
+            return;
+        }
+        Err(message) => {
+            println!("info string {message}");
+            return;
+        }
+    };
+
+    match (option.name, value) {
+        ("Minimal", Value::Check(v)) => {
+            settings.report = if v { Report::Minimal } else { Report::Full };
+            println!("info string set Minimal to {v}");
+        }
+        ("Clear Hash", Value::Button) => {
             shared.tt.clear(threads.len());
             println!("info string Hash cleared");
         }
-        ["name", "Hash", "value", v] => {
-            shared.tt.resize(threads.len(), v.parse().unwrap());
-            println!("info string set Hash to {v} MB");
-        }
-        ["name", "Threads", "value", v] => {
-            threads.set_count(v.parse().unwrap_or(1));
+        ("Hash", Value::Spin(v)) => match shared.tt.resize(threads.len(), v as usize) {
+            Some(size) if size == v as usize => println!("info string set Hash to {v} MB"),
+            Some(size) => println!("info string Could not allocate {v} MB for Hash, using {size} MB"),
+            None => println!("info string Could not allocate Hash, keeping {} MB", shared.tt.megabytes()),
+        },
+        ("Threads", Value::Spin(v)) => {
+            threads.set_count(v as usize);
+            if threads.len() < v as usize {
+                println!("info string Could not allocate the search data of {v} threads");
+            }
             println!("info string set Threads to {}", threads.len());
         }
-        ["name", "MoveOverhead", "value", v] => {
-            settings.move_overhead = v.parse().unwrap();
+        ("MoveOverhead", Value::Spin(v)) => {
+            settings.move_overhead = v as u64;
             println!("info string set MoveOverhead to {v} ms");
         }
         #[cfg(feature = "syzygy")]
-        ["name", "SyzygyPath", "value", v] => match crate::tb::initialize(v) {
-            Some(size) => println!("info string Loaded Syzygy tablebases with {size} pieces"),
-            None => eprintln!("Failed to load Syzygy tablebases"),
+        ("SyzygyPath", Value::String(v)) => match crate::tb::fathom::initialize(&v) {
+            Some(size) => {
+                shared.tablebases.set(Box::new(crate::tb::fathom::Fathom));
+                println!("info string Loaded Syzygy tablebases with {size} pieces");
+            }
+            None => {
+                shared.tablebases.set(Box::new(crate::tb::NoTablebases));
+                eprintln!("Failed to load Syzygy tablebases");
+            }
         },
-        ["name", "UCI_Chess960", "value", v] => {
-            settings.frc = v.parse().unwrap_or_default();
+        #[cfg(feature = "syzygy")]
+        ("SyzygyProbeDepth", Value::Spin(v)) => {
+            shared.syzygy_probe_depth.store(v as i32, Ordering::Relaxed);
+            println!("info string set SyzygyProbeDepth to {v}");
+        }
+        #[cfg(feature = "syzygy")]
+        ("SyzygyProbeLimit", Value::Spin(v)) => {
+            shared.syzygy_probe_limit.store(v as usize, Ordering::Relaxed);
+            println!("info string set SyzygyProbeLimit to {v}");
+        }
+        ("Syzygy50MoveRule", Value::Check(v)) => {
+            shared.syzygy_50_move_rule.store(v, Ordering::Relaxed);
+            println!("info string set Syzygy50MoveRule to {v}");
+        }
+        ("UCI_Chess960", Value::Check(v)) => {
+            settings.frc = v;
             println!("info string set UCI_Chess960 to {v}");
         }
-        ["name", "MultiPV", "value", v] => {
-            settings.multi_pv = v.parse().unwrap_or_default();
+        ("MultiPV", Value::Spin(v)) => {
+            settings.multi_pv = v as usize;
             println!("info string set MultiPV to {v}");
         }
-        #[cfg(feature = "spsa")]
-        ["name", name, "value", v] => {
-            crate::parameters::set_parameter(name, v);
-            println!("info string set {name} to {v}");
+        ("Ponder", Value::Check(v)) => println!("info string set Ponder to {v}"),
+        ("UCI_ShowWDL", Value::Check(v)) => {
+            shared.show_wdl.store(v, Ordering::Relaxed);
+            println!("info string set UCI_ShowWDL to {v}");
+        }
+        ("UCI_LimitStrength", Value::Check(v)) => {
+            settings.skill.limit_strength = v;
+            println!("info string set UCI_LimitStrength to {v}");
+        }
+        ("UCI_Elo", Value::Spin(v)) => {
+            settings.skill.elo = v as i32;
+            println!("info string set UCI_Elo to {v}");
+        }
+        ("Skill Level", Value::Spin(v)) => {
+            settings.skill.level = v as i32;
+            println!("info string set Skill Level to {v}");
+        }
+        ("SkillSeed", Value::Spin(v)) => {
+            settings.skill.seed = v as u64;
+            println!("info string set SkillSeed to {v}");
+        }
+        ("CurrMoveDelay", Value::Spin(v)) => {
+            shared.currmove_delay.store(v as u64, Ordering::Relaxed);
+            println!("info string set CurrMoveDelay to {v} ms");
+        }
+        ("HeartbeatInterval", Value::Spin(v)) => {
+            shared.heartbeat_interval.store(v as u64, Ordering::Relaxed);
+            println!("info string set HeartbeatInterval to {v} ms");
+        }
+        ("InvalidMoves", Value::Combo(v)) => {
+            settings.invalid_moves = if v == "Truncate" { InvalidMoves::Truncate } else { InvalidMoves::Reject };
+            println!("info string set InvalidMoves to {v}");
+        }
+        ("EvalFile", Value::String(v)) => load_network(threads, shared, &v),
+        ("OwnBook", Value::Check(v)) => {
+            settings.own_book = v;
+            println!("info string set OwnBook to {v}");
+        }
+        ("BookFile", Value::String(v)) if v.is_empty() => {
+            settings.book = None;
+            println!("info string Book unloaded");
         }
-        _ => eprintln!("Unknown option: '{}'", tokens.join(" ").trim_end()),
+        ("BookFile", Value::String(v)) => match Book::load(&v) {
+            Ok(book) => {
+                println!("info string Loaded book '{v}' with {} entries", book.len());
+                settings.book = Some(book);
+            }
+            Err(e) => {
+                settings.book = None;
+                println!("info string Failed to load book '{v}': {e}");
+            }
+        },
+        ("BookDepth", Value::Spin(v)) => {
+            settings.book_depth = v as usize;
+            println!("info string set BookDepth to {v}");
+        }
+        ("BookSelection", Value::Combo(v)) => {
+            settings.book_selection = if v == "Best" { Selection::Best } else { Selection::Weighted };
+            println!("info string set BookSelection to {v}");
+        }
+        ("EndgameTableCache", Value::String(v)) => {
+            settings.endgame_table_cache = v;
+            println!("info string set EndgameTableCache to {}", settings.endgame_table_cache);
+        }
+        ("EndgameTables", Value::String(v)) => {
+            // Generating a table can take minutes, which must not block the UCI loop
+            let cache = settings.endgame_table_cache.clone();
+            std::thread::spawn(move || match retrograde::load(&v, &cache) {
+                Ok(0) => println!("info string Endgame tables unloaded"),
+                Ok(count) => println!("info string Loaded {count} endgame tables for {v}"),
+                Err(message) => println!("info string {message}"),
+            });
+        }
+        ("Contempt", Value::Spin(v)) => {
+            settings.contempt.value = v as i32;
+            println!("info string set Contempt to {v}");
+        }
+        ("Analysis Contempt", Value::Combo(v)) => {
+            settings.contempt.analysis = match v {
+                "White" => AnalysisContempt::White,
+                "Black" => AnalysisContempt::Black,
+                "Both" => AnalysisContempt::Both,
+                _ => AnalysisContempt::Off,
+            };
+            println!("info string set Analysis Contempt to {v}");
+        }
+        ("UCI_AnalyseMode", Value::Check(v)) => {
+            settings.contempt.analyse_mode = v;
+            println!("info string set UCI_AnalyseMode to {v}");
+        }
+        ("UCI_Opponent", Value::String(v)) => {
+            settings.contempt.opponent_elo = contempt::parse_opponent(&v);
+            println!("info string set UCI_Opponent to {v}");
+        }
+        ("UCI_RatingAdv", Value::Spin(v)) => {
+            settings.contempt.rating_adv = contempt::parse_rating_adv(v);
+            println!("info string set UCI_RatingAdv to {v}");
+        }
+        ("Deterministic", Value::Check(v)) => {
+            // A search repeats only after the same commands since `ucinewgame` or this reset,
+            // which also recreates the search data of the threads in the chosen mode
+            shared.deterministic.store(v, Ordering::Relaxed);
+            reset(threads, shared);
+            println!("info string set Deterministic to {v}");
+        }
+        (name, value) => println!("info string Unhandled value {value:?} for option '{name}'"),
+    }
+}
+
+/// Loads the network at `path` (or the embedded one if `path` is empty) into every NUMA replica.
+fn load_network(threads: &mut ThreadPool, shared: &Arc<SharedContext>, path: &str) {
+    match crate::nnue::set_network(path) {
+        Ok(()) if path.is_empty() => println!("info string Using the embedded network"),
+        Ok(()) => println!("info string Loaded network '{path}'"),
+        Err(e) => println!("info string Failed to load network '{path}': {e}, using the embedded network"),
     }
+
+    shared.parameters.refresh();
+    threads.clear();
 }
 
 fn eval(td: &mut ThreadData, board: &Board) {
@@ -385,51 +762,101 @@ fn eval(td: &mut ThreadData, board: &Board) {
     let final_eval = td.nnue.evaluate(board);
     let final_total = (if side == Color::White { final_eval } else { -final_eval }) as f32 / 100.0;
     println!("\nNNUE evaluation        {final_total:+.2} (White's POV)");
+
+    let wdl = win_rate_model(final_eval, board);
+    let wdl = if side == Color::White { wdl } else { wdl.flip() };
+    println!(
+        "WDL model              W {:.1}% D {:.1}% L {:.1}% (White's POV)",
+        wdl.win as f32 / 10.0,
+        wdl.draw as f32 / 10.0,
+        wdl.loss as f32 / 10.0
+    );
 }
 
-fn parse_limits(color: Color, tokens: &[&str]) -> Limits {
-    if let ["infinite"] = tokens {
-        return Limits::Infinite;
+/// Splits the `searchmoves` list off the `go` arguments. The list runs until the next `go` keyword,
+/// and only moves that are legal in the given position are kept.
+fn split_searchmoves<'a>(board: &Board, tokens: &[&'a str]) -> (Vec<&'a str>, Vec<Move>) {
+    const GO_KEYWORDS: &[&str] =
+        &["wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "infinite", "ponder"];
+
+    let Some(start) = tokens.iter().position(|&token| token == "searchmoves") else {
+        return (tokens.to_vec(), Vec::new());
+    };
+
+    let end = tokens[start + 1..]
+        .iter()
+        .position(|token| GO_KEYWORDS.contains(token))
+        .map_or(tokens.len(), |offset| start + 1 + offset);
+
+    let mut searchmoves = Vec::new();
+
+    for &token in &tokens[start + 1..end] {
+        match board.find_uci_move(token) {
+            Some(mv) if !searchmoves.contains(&mv) => searchmoves.push(mv),
+            Some(_) => (),
+            None => println!("info string Ignoring illegal searchmove '{token}'"),
+        }
     }
 
-    let mut main = None;
-    let mut inc = None;
-    let mut moves = None;
+    if searchmoves.is_empty() && end > start + 1 {
+        println!("info string No legal searchmoves, searching every move");
+    }
 
-    for chunk in tokens.chunks(2) {
-        if let [name, value] = *chunk {
-            let Ok(value) = value.parse::<u64>() else {
-                continue;
-            };
+    let remaining = tokens[..start].iter().chain(&tokens[end..]).copied().collect();
+    (remaining, searchmoves)
+}
 
-            match name {
-                "depth" if value > 0 => return Limits::Depth(value as i32),
-                "movetime" if value > 0 => return Limits::Time(value),
-                "nodes" if value > 0 => return Limits::Nodes(value),
-                "mate" if value > 0 => return Limits::Mate(value),
+fn parse_limits(color: Color, tokens: &[&str]) -> Limits {
+    let mut limits = Limits::default();
 
-                "wtime" if Color::White == color => main = Some(value),
-                "btime" if Color::Black == color => main = Some(value),
-                "winc" if Color::White == color => inc = Some(value),
-                "binc" if Color::Black == color => inc = Some(value),
-                "movestogo" => moves = Some(value),
+    let mut main = None;
+    let mut inc = None;
+    let mut moves = None;
 
-                _ => continue,
+    let mut tokens = tokens.iter().peekable();
+
+    while let Some(&name) = tokens.next() {
+        match name {
+            "infinite" => limits.infinite = true,
+            "ponder" => limits.ponder = true,
+            _ => {
+                let Some(value) = tokens.next_if(|v| v.parse::<u64>().is_ok()).and_then(|v| v.parse::<u64>().ok())
+                else {
+                    continue;
+                };
+
+                match name {
+                    "depth" if value > 0 => limits.depth = Some(value as i32),
+                    "movetime" if value > 0 => limits.movetime = Some(value),
+                    "nodes" if value > 0 => limits.nodes = Some(value),
+                    "mate" if value > 0 => limits.mate = Some(value),
+
+                    "wtime" if Color::White == color => main = Some(value),
+                    "btime" if Color::Black == color => main = Some(value),
+                    "winc" if Color::White == color => inc = Some(value),
+                    "binc" if Color::Black == color => inc = Some(value),
+                    "movestogo" if value > 0 => moves = Some(value),
+
+                    _ => continue,
+                }
             }
         }
     }
 
-    if main.is_none() && inc.is_none() {
-        return Limits::Infinite;
+    if main.is_some() || inc.is_some() {
+        limits.clock = Some(Clock {
+            time: main.unwrap_or_default(),
+            increment: inc.unwrap_or_default(),
+            moves_to_go: moves,
+        });
     }
 
-    let main = main.unwrap_or_default();
-    let inc = inc.unwrap_or_default();
-
-    match moves {
-        Some(moves) => Limits::Cyclic(main, inc, moves),
-        None => Limits::Fischer(main, inc),
+    // A bare `go` searches until `stop`
+    if limits.depth.is_none() && limits.nodes.is_none() && limits.mate.is_none() && !limits.is_timed() {
+        limits.infinite = true;
     }
+
+    limits
 }
 
 #[cfg(test)]
@@ -484,9 +911,22 @@ mod tests {
     }
 
     #[test]
-    fn test_position_invalid_move_ignored() {
+    fn test_position_invalid_move_rejected() {
         let board = test_position_helper(&["moves", "e2e4", "invalid", "e7e5"]);
+        assert_eq!(board.to_fen(), Board::starting_position().to_fen());
+
+        let board = test_position_helper(&["moves", "e2e4", "e7e5", "e1e2", "e8e7", "e2e4"]);
+        assert_eq!(board.to_fen(), Board::starting_position().to_fen());
+    }
+
+    #[test]
+    fn test_position_invalid_move_truncated() {
+        let settings = Settings { invalid_moves: InvalidMoves::Truncate, ..Default::default() };
+        let mut board = Board::starting_position();
+
+        position(&mut board, &settings, &["startpos", "moves", "e2e4", "e7e5", "e1e3", "b8c6"]);
         assert_eq!(board.side_to_move(), Color::White);
+        assert_eq!(board.fullmove_number(), 2);
     }
 
     #[test]
@@ -537,13 +977,124 @@ mod tests {
     fn test_make_uci_move_invalid() {
         let mut board = Board::starting_position();
         let fen_before = board.to_fen();
-        make_uci_move(&mut board, "invalid_move");
+        assert!(!make_uci_move(&mut board, "invalid_move"));
+        assert!(!make_uci_move(&mut board, "e2e5"));
         assert_eq!(board.to_fen(), fen_before);
     }
 
+    #[test]
+    fn test_parse_limits_combined() {
+        let limits = parse_limits(Color::White, &["depth", "20", "movetime", "5000", "nodes", "100000", "mate", "3"]);
+
+        assert_eq!(limits.depth, Some(20));
+        assert_eq!(limits.movetime, Some(5000));
+        assert_eq!(limits.nodes, Some(100000));
+        assert_eq!(limits.mate, Some(3));
+        assert!(limits.clock.is_none());
+        assert!(!limits.infinite);
+    }
+
+    #[test]
+    fn test_parse_limits_clock_with_depth() {
+        let tokens =
+            ["wtime", "60000", "btime", "50000", "winc", "1000", "binc", "500", "movestogo", "20", "depth", "8"];
+
+        let white = parse_limits(Color::White, &tokens);
+        let clock = white.clock.unwrap();
+        assert_eq!((clock.time, clock.increment, clock.moves_to_go), (60000, 1000, Some(20)));
+        assert_eq!(white.depth, Some(8));
+
+        let black = parse_limits(Color::Black, &tokens);
+        let clock = black.clock.unwrap();
+        assert_eq!((clock.time, clock.increment, clock.moves_to_go), (50000, 500, Some(20)));
+    }
+
+    #[test]
+    fn test_parse_limits_flags() {
+        let limits = parse_limits(Color::White, &["ponder", "wtime", "1000", "btime", "1000"]);
+        assert!(limits.ponder);
+        assert!(!limits.infinite);
+        assert_eq!(limits.clock.unwrap().time, 1000);
+
+        let limits = parse_limits(Color::White, &["infinite", "depth", "10"]);
+        assert!(limits.infinite);
+        assert_eq!(limits.depth, Some(10));
+
+        assert!(parse_limits(Color::White, &[]).infinite);
+        assert!(parse_limits(Color::White, &["depth", "0"]).infinite);
+    }
+
+    #[test]
+    fn test_parse_limits_skips_unknown_tokens() {
+        let limits = parse_limits(Color::White, &["foo", "depth", "5", "bar", "1", "nodes", "x", "movetime", "100"]);
+
+        assert_eq!(limits.depth, Some(5));
+        assert_eq!(limits.nodes, None);
+        assert_eq!(limits.movetime, Some(100));
+    }
+
+    #[test]
+    fn test_split_searchmoves() {
+        let board = Board::starting_position();
+        let (tokens, searchmoves) = split_searchmoves(&board, &["searchmoves", "e2e4", "d2d4", "depth", "5"]);
+
+        assert_eq!(tokens, ["depth", "5"]);
+        assert_eq!(searchmoves.iter().map(|mv| mv.to_uci(&board)).collect::<Vec<_>>(), ["e2e4", "d2d4"]);
+    }
+
+    #[test]
+    fn test_split_searchmoves_skips_illegal_and_duplicates() {
+        let board = Board::starting_position();
+        let (tokens, searchmoves) =
+            split_searchmoves(&board, &["wtime", "1000", "searchmoves", "e2e5", "g1f3", "g1f3", "btime", "1000"]);
+
+        assert_eq!(tokens, ["wtime", "1000", "btime", "1000"]);
+        assert_eq!(searchmoves.iter().map(|mv| mv.to_uci(&board)).collect::<Vec<_>>(), ["g1f3"]);
+    }
+
+    #[test]
+    fn test_split_searchmoves_absent() {
+        let board = Board::starting_position();
+        let (tokens, searchmoves) = split_searchmoves(&board, &["depth", "5"]);
+
+        assert_eq!(tokens, ["depth", "5"]);
+        assert!(searchmoves.is_empty());
+    }
+
     #[test]
     fn test_position_moves_without_startpos_ignored() {
         let board = test_position_helper(&["moves", "e2e4", "e7e5"]);
         assert_eq!(board.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
     }
+
+    #[test]
+    fn test_option_names_are_case_insensitive() {
+        let registry = options::registry();
+
+        let (option, value) = options::parse(&registry, &["name", "multipv", "value", "3"]).unwrap();
+        assert_eq!((option.name, value), ("MultiPV", Value::Spin(3)));
+
+        let (option, value) = options::parse(&registry, &["name", "skill", "LEVEL", "value", "5"]).unwrap();
+        assert_eq!((option.name, value), ("Skill Level", Value::Spin(5)));
+
+        let (option, value) = options::parse(&registry, &["name", "clear", "hash"]).unwrap();
+        assert_eq!((option.name, value), ("Clear Hash", Value::Button));
+    }
+
+    #[test]
+    fn test_option_values_are_validated() {
+        let registry = options::registry();
+
+        assert!(options::parse(&registry, &["name", "Hash", "value", "abc"]).is_err());
+        assert!(options::parse(&registry, &["name", "Hash"]).is_err());
+        assert!(options::parse(&registry, &["name", "Ponder", "value", "yes"]).is_err());
+        assert!(options::parse(&registry, &["name", "NoSuchOption", "value", "1"]).is_err());
+        assert!(options::parse(&registry, &["value", "1"]).is_err());
+
+        let (_, value) = options::parse(&registry, &["name", "MoveOverhead", "value", "99999"]).unwrap();
+        assert_eq!(value, Value::Spin(2000));
+
+        let (_, value) = options::parse(&registry, &["name", "UCI_Chess960", "value", "TRUE"]).unwrap();
+        assert_eq!(value, Value::Check(true));
+    }
 }
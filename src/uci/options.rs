@@ -0,0 +1,167 @@
+use std::fmt;
+
+use crate::{
+    skill::{MAX_ELO, MAX_LEVEL, MIN_ELO},
+    threadpool::ThreadPool,
+    transposition::DEFAULT_TT_SIZE,
+    types::MAX_MOVES,
+};
+
+#[derive(Copy, Clone)]
+pub enum Kind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: &'static str, vars: &'static [&'static str] },
+    Button,
+    String { default: &'static str },
+}
+
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: Kind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Check(bool),
+    Spin(i64),
+    Combo(&'static str),
+    Button,
+    String(String),
+}
+
+/// Returns every option the engine understands, in the order they are reported by `uci`.
+pub fn registry() -> Vec<UciOption> {
+    #[allow(unused_mut)]
+    let mut options = vec![
+        UciOption::spin("Hash", DEFAULT_TT_SIZE as i64, 1, 262144),
+        UciOption::spin("Threads", 1, 1, ThreadPool::available_threads() as i64),
+        UciOption::spin("MoveOverhead", 100, 0, 2000),
+        UciOption::check("Minimal", false),
+        UciOption::button("Clear Hash"),
+        UciOption::check("UCI_Chess960", false),
+        UciOption::spin("MultiPV", 1, 1, MAX_MOVES as i64),
+        UciOption::check("Ponder", false),
+        UciOption::check("UCI_ShowWDL", false),
+        UciOption::check("UCI_LimitStrength", false),
+        UciOption::spin("UCI_Elo", MAX_ELO as i64, MIN_ELO as i64, MAX_ELO as i64),
+        UciOption::spin("Skill Level", MAX_LEVEL as i64, 0, MAX_LEVEL as i64),
+        UciOption::spin("SkillSeed", 0, 0, u32::MAX as i64),
+        UciOption::spin("CurrMoveDelay", 3000, 0, 60000),
+        UciOption::spin("HeartbeatInterval", 1000, 0, 60000),
+        UciOption::combo("InvalidMoves", "Reject", &["Reject", "Truncate"]),
+        UciOption::string("EvalFile", ""),
+        UciOption::check("OwnBook", false),
+        UciOption::string("BookFile", ""),
+        UciOption::spin("BookDepth", 20, 1, 100),
+        UciOption::combo("BookSelection", "Weighted", &["Weighted", "Best"]),
+        UciOption::string("EndgameTableCache", ""),
+        UciOption::string("EndgameTables", ""),
+        // Applies to the generated endgame tables as well as to the Syzygy ones
+        UciOption::check("Syzygy50MoveRule", true),
+        UciOption::spin("Contempt", 0, -100, 100),
+        UciOption::combo("Analysis Contempt", "Off", &["Off", "White", "Black", "Both"]),
+        UciOption::check("UCI_AnalyseMode", false),
+        UciOption::string("UCI_Opponent", ""),
+        UciOption::spin("UCI_RatingAdv", 0, -10000, 10000),
+        UciOption::check("Deterministic", false),
+    ];
+
+    #[cfg(feature = "syzygy")]
+    options.extend([
+        UciOption::string("SyzygyPath", ""),
+        UciOption::spin("SyzygyProbeDepth", 1, 1, 100),
+        UciOption::spin("SyzygyProbeLimit", 7, 0, 7),
+    ]);
+
+    options
+}
+
+/// Parses the arguments of `setoption` (`name <id> [value <x>]`) against the registry.
+/// Option names are matched case-insensitively. Out-of-range spin values are clamped,
+/// any other malformed value is rejected with a message suitable for `info string`.
+pub fn parse<'a>(options: &'a [UciOption], tokens: &[&str]) -> Result<(&'a UciOption, Value), String> {
+    let ["name", tokens @ ..] = tokens else {
+        return Err("Missing option name".to_string());
+    };
+
+    let (name, value) = match tokens.iter().position(|&token| token == "value") {
+        Some(index) => (tokens[..index].join(" "), Some(tokens[index + 1..].join(" "))),
+        None => (tokens.join(" "), None),
+    };
+
+    let Some(option) = options.iter().find(|option| option.name.eq_ignore_ascii_case(&name)) else {
+        return Err(format!("Unknown option '{name}'"));
+    };
+
+    let invalid = |value: &str| format!("Invalid value '{value}' for option '{}'", option.name);
+
+    let value = match (option.kind, value) {
+        (Kind::Button, _) => Value::Button,
+        (_, None) => return Err(format!("Missing value for option '{}'", option.name)),
+        (Kind::Check { .. }, Some(value)) => match value.to_ascii_lowercase().as_str() {
+            "true" => Value::Check(true),
+            "false" => Value::Check(false),
+            _ => return Err(invalid(&value)),
+        },
+        (Kind::Spin { min, max, .. }, Some(value)) => {
+            let parsed = value.parse::<i64>().map_err(|_| invalid(&value))?;
+            let clamped = parsed.clamp(min, max);
+
+            if clamped != parsed {
+                println!("info string Value {parsed} for option '{}' is out of range, using {clamped}", option.name);
+            }
+            Value::Spin(clamped)
+        }
+        (Kind::Combo { vars, .. }, Some(value)) => match vars.iter().find(|var| var.eq_ignore_ascii_case(&value)) {
+            Some(var) => Value::Combo(var),
+            None => return Err(invalid(&value)),
+        },
+        (Kind::String { .. }, Some(value)) => match value.as_str() {
+            "<empty>" => Value::String(String::new()),
+            _ => Value::String(value),
+        },
+    };
+
+    Ok((option, value))
+}
+
+impl UciOption {
+    pub const fn check(name: &'static str, default: bool) -> Self {
+        Self { name, kind: Kind::Check { default } }
+    }
+
+    pub const fn spin(name: &'static str, default: i64, min: i64, max: i64) -> Self {
+        Self { name, kind: Kind::Spin { default, min, max } }
+    }
+
+    pub const fn combo(name: &'static str, default: &'static str, vars: &'static [&'static str]) -> Self {
+        Self { name, kind: Kind::Combo { default, vars } }
+    }
+
+    pub const fn button(name: &'static str) -> Self {
+        Self { name, kind: Kind::Button }
+    }
+
+    pub const fn string(name: &'static str, default: &'static str) -> Self {
+        Self { name, kind: Kind::String { default } }
+    }
+}
+
+impl fmt::Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+
+        match self.kind {
+            Kind::Check { default } => write!(f, "check default {default}"),
+            Kind::Spin { default, min, max } => write!(f, "spin default {default} min {min} max {max}"),
+            Kind::Combo { default, vars } => {
+                write!(f, "combo default {default}")?;
+                vars.iter().try_for_each(|var| write!(f, " var {var}"))
+            }
+            Kind::Button => write!(f, "button"),
+            Kind::String { default: "" } => write!(f, "string default <empty>"),
+            Kind::String { default } => write!(f, "string default {default}"),
+        }
+    }
+}
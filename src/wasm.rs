@@ -35,10 +35,14 @@ impl Engine {
         self.board = Board::from_fen(fen).unwrap_or_else(|_| Board::starting_position());
     }
 
-    pub fn make_move(&mut self, uci_move: &str) {
-        let moves = self.board.generate_all_moves();
-        if let Some(mv) = moves.iter().map(|e| e.mv).find(|m| m.to_uci(&self.board) == uci_move) {
-            self.board.make_move(mv, &mut NullBoardObserver);
+    /// Plays `uci_move`, leaving the board unchanged and returning an error if it is not legal.
+    pub fn make_move(&mut self, uci_move: &str) -> Result<(), JsError> {
+        match self.board.find_uci_move(uci_move) {
+            Some(mv) => {
+                self.board.make_move(mv, &mut NullBoardObserver);
+                Ok(())
+            }
+            None => Err(JsError::new(&format!("Illegal move '{uci_move}'"))),
         }
     }
 
@@ -52,18 +56,18 @@ impl Engine {
 
     pub fn go_uci(&mut self, depth: u32, nodes: u32, multi_pv: u32, on_info: Option<JsFunction>) {
         let multi_pv = (multi_pv as usize).max(1);
-        let limits = if nodes > 0 {
-            Limits::Nodes(nodes as u64)
-        } else if depth > 0 {
-            Limits::Depth(depth as i32)
-        } else {
-            Limits::Infinite
+        let limits = Limits {
+            depth: (depth > 0).then_some(depth as i32),
+            nodes: (nodes > 0).then_some(nodes as u64),
+            infinite: depth == 0 && nodes == 0,
+            ..Default::default()
         };
         self.run_search(limits, multi_pv, on_info);
     }
 
     pub fn go_movetime(&mut self, ms: u32, multi_pv: u32, on_info: Option<JsFunction>) {
-        self.run_search(Limits::Time(ms as u64), (multi_pv as usize).max(1), on_info);
+        let limits = Limits { movetime: Some(ms as u64), ..Default::default() };
+        self.run_search(limits, (multi_pv as usize).max(1), on_info);
     }
 
     pub fn take_output(&mut self) -> String {
@@ -73,7 +77,7 @@ impl Engine {
     fn run_search(&mut self, limits: Limits, multi_pv: usize, on_info: Option<JsFunction>) {
         crate::thread::WASM_CALLBACK.with(|c| *c.borrow_mut() = on_info);
         let tm = TimeManager::new(limits, self.board.fullmove_number(), 0);
-        self.threads.execute_searches(tm, Report::Full, multi_pv, &self.board, &self.shared);
+        self.threads.execute_searches(tm, Report::Full, multi_pv, &[], &self.board, &self.shared);
         crate::thread::WASM_CALLBACK.with(|c| *c.borrow_mut() = None);
     }
 